@@ -1,8 +1,27 @@
 use std::env;
 use std::path::Path;
+use std::process::Command;
 
 fn main() {
     let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     let root_dir = Path::new(&manifest_dir).parent().unwrap();
     println!("cargo:rustc-env=UTRACE_DIR={}", root_dir.display());
+
+    // The plugin embeds this compiler, so records are tagged with its version.
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let output = Command::new(&rustc).arg("-V").output().unwrap();
+    let rustc_version = String::from_utf8_lossy(&output.stdout);
+    println!(
+        "cargo:rustc-env=UTRACE_RUSTC_VERSION={}",
+        rustc_version.trim()
+    );
+
+    // The plugin links to `rustc_driver` of this sysroot and runs its rustc and cargo.
+    let output = Command::new(&rustc)
+        .arg("--print")
+        .arg("sysroot")
+        .output()
+        .unwrap();
+    let sysroot = String::from_utf8_lossy(&output.stdout);
+    println!("cargo:rustc-env=UTRACE_SYSROOT={}", sysroot.trim());
 }
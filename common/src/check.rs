@@ -0,0 +1,124 @@
+//! The policy of `check`: limits on the unsafe items of the selected crates and denials.
+//!
+//! `check` fails on the violations of the policy, and the SARIF log reports them as results.
+
+use crate::record::{Record, UnsafeKind};
+use crate::records::Records;
+use crate::report::api_calls;
+
+use serde::Serialize;
+
+/// The limits and denials of `check`, from its flags and the project configuration.
+#[derive(Debug, Default, Clone)]
+pub struct Policy {
+    pub max_functions: Option<usize>,
+    pub max_blocks: Option<usize>,
+    pub max_impls: Option<usize>,
+    pub max_traits: Option<usize>,
+    pub deny_uninit: bool,
+    // paths of functions whose calls are denied, e.g. `core::mem::transmute`
+    pub deny_apis: Vec<String>,
+}
+
+/// A limit of the policy exceeded by the selected crates.
+#[derive(Debug)]
+pub struct Exceeded {
+    // e.g. `max-blocks`
+    pub rule: &'static str,
+    pub kind: UnsafeKind,
+    pub count: usize,
+    pub limit: usize,
+}
+
+impl Exceeded {
+    // e.g. `Too many unsafe blocks: 5 > 3`
+    pub fn message(&self) -> String {
+        let name = match self.kind {
+            UnsafeKind::Function => "functions",
+            UnsafeKind::Block => "blocks",
+            UnsafeKind::Impl => "impls",
+            UnsafeKind::Trait => "traits",
+        };
+        format!("Too many unsafe {}: {} > {}", name, self.count, self.limit)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Violation {
+    // e.g. `max-blocks`, `deny-uninit` or `deny-api`
+    pub rule: String,
+    pub message: String,
+}
+
+impl Policy {
+    fn limits(&self) -> [(&'static str, UnsafeKind, Option<usize>); 4] {
+        [
+            ("max-functions", UnsafeKind::Function, self.max_functions),
+            ("max-blocks", UnsafeKind::Block, self.max_blocks),
+            ("max-impls", UnsafeKind::Impl, self.max_impls),
+            ("max-traits", UnsafeKind::Trait, self.max_traits),
+        ]
+    }
+
+    /// The limits the unsafe items of `selected` exceed, counted over all of them.
+    pub fn exceeded(&self, records: &Records, selected: &[&Record]) -> Vec<Exceeded> {
+        let count = |kind| {
+            selected
+                .iter()
+                .flat_map(|record| records.own_items(record))
+                .filter(|item| item.kind == kind)
+                .count()
+        };
+
+        self.limits()
+            .into_iter()
+            .filter_map(|(rule, kind, limit)| {
+                let limit = limit?;
+                let count = count(kind.clone());
+                (count > limit).then_some(Exceeded {
+                    rule,
+                    kind,
+                    count,
+                    limit,
+                })
+            })
+            .collect()
+    }
+
+    /// Every violation of the policy by `selected`, in the order `check` prints them.
+    pub fn violations(&self, records: &Records, selected: &[&Record]) -> Vec<Violation> {
+        let mut violations: Vec<_> = self
+            .exceeded(records, selected)
+            .into_iter()
+            .map(|exceeded| Violation {
+                rule: exceeded.rule.to_string(),
+                message: exceeded.message(),
+            })
+            .collect();
+
+        if self.deny_uninit {
+            let findings: usize = selected.iter().map(|record| record.findings.len()).sum();
+            if findings > 0 {
+                violations.push(Violation {
+                    rule: "deny-uninit".to_string(),
+                    message: format!("Uninitialized memory found in {} functions", findings),
+                });
+            }
+        }
+
+        for record in selected {
+            for (caller, edge) in api_calls(records, record, &self.deny_apis) {
+                violations.push(Violation {
+                    rule: "deny-api".to_string(),
+                    message: format!(
+                        "Dangerous API {} called in {}",
+                        records.name(&edge.callee),
+                        records.name(caller)
+                    ),
+                });
+            }
+        }
+
+        violations
+    }
+}
@@ -1,5 +1,6 @@
+// Set by `build.rs`, and by cargo when utrace is run with `cargo run`.
 pub fn root_dir() -> String {
-    std::env::var("UTRACE_DIR").unwrap()
+    std::env::var("UTRACE_DIR").unwrap_or_else(|_| env!("UTRACE_DIR").to_string())
 }
 
 pub fn plugin_dir() -> String {
@@ -7,16 +8,50 @@ pub fn plugin_dir() -> String {
 }
 
 pub fn plugin_bin() -> String {
-    format!(
-        "{}/target/x86_64-unknown-linux-gnu/release/utrace_plugin",
-        root_dir()
-    )
+    format!("{}/target/release/utrace_plugin", root_dir())
 }
 
-pub fn toolchain_dir() -> String {
-    format!("{}/toolchain", root_dir())
+// The toolchain the plugin is built with
+pub fn sysroot() -> String {
+    env!("UTRACE_SYSROOT").to_string()
 }
 
+pub fn sysroot_lib() -> String {
+    format!("{}/lib", sysroot())
+}
+
+pub fn rustc_bin() -> String {
+    format!("{}/bin/rustc", sysroot())
+}
+
+pub fn cargo_bin() -> String {
+    format!("{}/bin/cargo", sysroot())
+}
+
+// Set by the driver for each analysed project.
+// Compiling a single file with the plugin falls back to `out` of the checkout.
 pub fn out_dir() -> String {
-    format!("{}/out", root_dir())
+    std::env::var("UTRACE_OUT_DIR").unwrap_or_else(|_| format!("{}/out", root_dir()))
+}
+
+// The target given to cargo, either by `--target` or by `build.target` of the project
+pub fn build_target() -> Option<String> {
+    std::env::var("UTRACE_BUILD_TARGET").ok()
+}
+
+// Manifest directories of the packages only used by build scripts and proc-macros, set by the driver
+pub fn host_packages() -> Vec<std::path::PathBuf> {
+    manifest_dirs("UTRACE_HOST_PACKAGES")
+}
+
+// Manifest directories of the packages only used by the tests of the workspace, set by the driver
+pub fn dev_packages() -> Vec<std::path::PathBuf> {
+    manifest_dirs("UTRACE_DEV_PACKAGES")
+}
+
+// One directory per line, as paths may contain commas
+fn manifest_dirs(var: &str) -> Vec<std::path::PathBuf> {
+    std::env::var(var)
+        .map(|dirs| dirs.lines().map(std::path::PathBuf::from).collect())
+        .unwrap_or_default()
 }
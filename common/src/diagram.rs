@@ -0,0 +1,452 @@
+//! The call graph as Graphviz DOT and Mermaid diagrams, e.g. for design reviews.
+//!
+//! Unsafe functions are coloured by the kind of their unsafe item, the functions of a crate
+//! are grouped in a cluster and calls are styled by their kind. The graph can be restricted
+//! to the functions reachable from or reaching some functions, and collapsed by module or crate.
+
+use crate::record::{CallKind, NodeId, UnsafeKind};
+use crate::records::Records;
+use crate::report::selected;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Collapse {
+    Module,
+    Crate,
+}
+
+pub struct Options<'a> {
+    // Only the functions reachable from these, when not empty
+    pub from: Vec<&'a NodeId>,
+    // Only the functions from which these are reachable, when not empty
+    pub to: Vec<&'a NodeId>,
+    pub collapse: Option<Collapse>,
+}
+
+// A function, or the functions of a module or a crate once collapsed
+struct Node {
+    label: String,
+    // the first unsafe kind of its functions, in the order of `UnsafeKind`
+    kind: Option<UnsafeKind>,
+    cluster: Option<String>,
+}
+
+struct Diagram {
+    nodes: Vec<Node>,
+    // (caller, callee) -> the kinds of the calls
+    edges: BTreeMap<(usize, usize), BTreeSet<CallKind>>,
+}
+
+// The segments of a path, e.g. `vmsa`, `<impl page_table::PageTable<A, L, E, N>>` and `new_in`
+fn segments(path: &str) -> Vec<&str> {
+    let bytes = path.as_bytes();
+    let mut segments = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'<' | b'{' | b'(' | b'[' => depth += 1,
+            // but not the `->` of fn pointers
+            b'>' if i > 0 && bytes[i - 1] == b'-' => {}
+            b'>' | b'}' | b')' | b']' => depth -= 1,
+            b':' if depth == 0 && bytes.get(i + 1) == Some(&b':') => {
+                segments.push(&path[start..i]);
+                start = i + 2;
+                i += 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    segments.push(&path[start..]);
+    segments
+}
+
+// The leading segments naming the crate and its modules, i.e. up to the first type, impl or item.
+// A qualified path, e.g. `<alloc::vec::Vec<T> as core::ops::Drop>::drop`, is in those of its self type.
+// Closures and consts, e.g. `vmsa::map::{closure#0}`, are in those of their function.
+// Only a guess for nodes without a recorded module, as nested functions look like modules.
+fn modules(path: &str) -> Vec<&str> {
+    let is_module = |segment: &&str| {
+        segment.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    let parts = segments(path);
+    match parts[0].strip_prefix('<') {
+        Some(qualified) => segments(qualified)
+            .into_iter()
+            .take_while(is_module)
+            .collect(),
+        None => {
+            let item = parts
+                .iter()
+                .position(|segment| segment.starts_with('{'))
+                .unwrap_or(parts.len());
+            parts[..item.saturating_sub(1)]
+                .iter()
+                .copied()
+                .take_while(is_module)
+                .collect()
+        }
+    }
+}
+
+// Paths without a crate, e.g. the fn pointer types called through, are on their own.
+fn crate_of(records: &Records, id: &str) -> String {
+    let name = records.name(id);
+    let modules = match records.module(id) {
+        Some(module) => segments(module),
+        None => modules(name),
+    };
+    match modules.first() {
+        Some(krate) => krate.to_string(),
+        None => name.to_string(),
+    }
+}
+
+fn module_of(records: &Records, id: &str) -> String {
+    if let Some(module) = records.module(id) {
+        return module.to_string();
+    }
+    let modules = modules(records.name(id));
+    if modules.is_empty() {
+        crate_of(records, id)
+    } else {
+        modules.join("::")
+    }
+}
+
+impl Diagram {
+    fn new(records: &Records, filter: &Option<Vec<String>>, options: &Options) -> Self {
+        let mut ids: BTreeSet<&NodeId> = BTreeSet::new();
+        if options.from.is_empty() && options.to.is_empty() {
+            for record in selected(records, filter) {
+                for (caller, edges) in &record.graph {
+                    ids.insert(caller);
+                    ids.extend(edges.iter().map(|edge| &edge.callee));
+                }
+            }
+        } else {
+            let from: Option<BTreeSet<_>> = (!options.from.is_empty()).then(|| {
+                let reachable = options.from.iter().flat_map(|id| records.reachable(id));
+                options.from.iter().copied().chain(reachable).collect()
+            });
+            let to: Option<BTreeSet<_>> = (!options.to.is_empty()).then(|| {
+                let reaching = options.to.iter().flat_map(|id| records.reaching(id));
+                options.to.iter().copied().chain(reaching).collect()
+            });
+            ids = match (from, to) {
+                (Some(from), Some(to)) => from.intersection(&to).copied().collect(),
+                (from, to) => from.or(to).unwrap_or_default(),
+            };
+        }
+
+        let mut kinds: BTreeMap<&NodeId, &UnsafeKind> = BTreeMap::new();
+        for item in records.items() {
+            let kind = kinds.entry(&item.id).or_insert(&item.kind);
+            *kind = (*kind).min(&item.kind);
+        }
+
+        // Every function is a node of its own, or of its module or crate.
+        let mut nodes = Vec::new();
+        let mut groups: BTreeMap<String, usize> = BTreeMap::new();
+        let mut index: BTreeMap<&NodeId, usize> = BTreeMap::new();
+        let mut sizes: Vec<usize> = Vec::new();
+        for &id in &ids {
+            let name = records.name(id);
+            let key = match options.collapse {
+                None => id.to_string(),
+                Some(Collapse::Module) => module_of(records, id),
+                Some(Collapse::Crate) => crate_of(records, id),
+            };
+            let i = *groups.entry(key.clone()).or_insert_with(|| {
+                nodes.push(Node {
+                    label: if options.collapse.is_some() {
+                        key
+                    } else {
+                        name.to_string()
+                    },
+                    kind: None,
+                    cluster: (options.collapse != Some(Collapse::Crate))
+                        .then(|| crate_of(records, id)),
+                });
+                sizes.push(0);
+                nodes.len() - 1
+            });
+            sizes[i] += 1;
+            if let Some(&kind) = kinds.get(id) {
+                let node = &mut nodes[i];
+                if node.kind.as_ref().map_or(true, |first| kind < first) {
+                    node.kind = Some(kind.clone());
+                }
+            }
+            index.insert(id, i);
+        }
+        if options.collapse.is_some() {
+            for (node, size) in nodes.iter_mut().zip(sizes) {
+                let plural = if size == 1 { "" } else { "s" };
+                node.label = format!("{} ({} function{})", node.label, size, plural);
+            }
+        }
+
+        let mut edges: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+        for &caller in &ids {
+            for edge in records.callees(caller) {
+                let Some(&callee) = index.get(&edge.callee) else {
+                    continue;
+                };
+                let caller = index[caller];
+                // Calls within a module or a crate are left out once it is collapsed.
+                if options.collapse.is_some() && caller == callee {
+                    continue;
+                }
+                edges
+                    .entry((caller, callee))
+                    .or_default()
+                    .insert(edge.kind.clone());
+            }
+        }
+
+        Self { nodes, edges }
+    }
+
+    // The nodes of each cluster, the nodes without one first
+    fn clusters(&self) -> BTreeMap<Option<&str>, Vec<usize>> {
+        let mut clusters: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            clusters.entry(node.cluster.as_deref()).or_default().push(i);
+        }
+        clusters
+    }
+
+    // The kind shared by all the calls of an edge, if any
+    fn edge_kind(kinds: &BTreeSet<CallKind>) -> Option<&CallKind> {
+        match kinds.len() {
+            1 => kinds.first(),
+            _ => None,
+        }
+    }
+}
+
+fn color(kind: &UnsafeKind) -> &'static str {
+    match kind {
+        UnsafeKind::Function => "#f4a6a6",
+        UnsafeKind::Block => "#f9d28b",
+        UnsafeKind::Trait => "#a8c8f0",
+        UnsafeKind::Impl => "#c9b3f5",
+    }
+}
+
+fn dot_style(kind: Option<&CallKind>) -> &'static str {
+    match kind {
+        None | Some(CallKind::Direct) => "",
+        Some(CallKind::Method) => " [color=\"#555555\"]",
+        Some(CallKind::TraitDispatch) => " [style=dashed]",
+        Some(CallKind::FnPointer) => " [style=dotted]",
+        Some(CallKind::Drop) => " [style=bold, color=\"#888888\"]",
+        Some(CallKind::Closure) => " [arrowhead=empty]",
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The call graph as a Graphviz DOT digraph.
+pub fn dot(records: &Records, filter: &Option<Vec<String>>, options: &Options) -> String {
+    let diagram = Diagram::new(records, filter, options);
+    let mut out = String::new();
+    writeln!(out, "digraph utrace {{").unwrap();
+    writeln!(out, "    rankdir=LR;").unwrap();
+    writeln!(
+        out,
+        "    node [shape=box, fontname=\"monospace\", fontsize=10];"
+    )
+    .unwrap();
+
+    for (k, (cluster, nodes)) in diagram.clusters().into_iter().enumerate() {
+        let indent = if cluster.is_some() {
+            "        "
+        } else {
+            "    "
+        };
+        if let Some(cluster) = cluster {
+            writeln!(out, "    subgraph cluster_{} {{", k).unwrap();
+            writeln!(out, "        label=\"{}\";", dot_escape(cluster)).unwrap();
+        }
+        for i in nodes {
+            let node = &diagram.nodes[i];
+            let fill = match &node.kind {
+                Some(kind) => format!(", style=filled, fillcolor=\"{}\"", color(kind)),
+                None => String::new(),
+            };
+            let label = dot_escape(&node.label);
+            writeln!(out, "{}n{} [label=\"{}\"{}];", indent, i, label, fill).unwrap();
+        }
+        if cluster.is_some() {
+            writeln!(out, "    }}").unwrap();
+        }
+    }
+
+    for ((caller, callee), kinds) in &diagram.edges {
+        let style = dot_style(Diagram::edge_kind(kinds));
+        writeln!(out, "    n{} -> n{}{};", caller, callee, style).unwrap();
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+fn mermaid_class(kind: &UnsafeKind) -> &'static str {
+    match kind {
+        UnsafeKind::Function => "unsafeFunction",
+        UnsafeKind::Block => "unsafeBlock",
+        UnsafeKind::Trait => "unsafeTrait",
+        UnsafeKind::Impl => "unsafeImpl",
+    }
+}
+
+fn mermaid_arrow(kind: Option<&CallKind>) -> String {
+    match kind {
+        None | Some(CallKind::Direct) | Some(CallKind::Method) => "-->".to_string(),
+        Some(CallKind::Drop) => "==>|drop|".to_string(),
+        Some(kind) => format!("-.->|{}|", kind),
+    }
+}
+
+// Like `dot_style`, the arrows which only differ by their colour
+fn mermaid_link_style(kind: Option<&CallKind>) -> Option<&'static str> {
+    match kind {
+        Some(CallKind::Method) => Some("stroke:#555555"),
+        Some(CallKind::Drop) => Some("stroke:#888888"),
+        _ => None,
+    }
+}
+
+// Mermaid labels are quoted, and `<`, `>` and `#` start markup and entities.
+fn mermaid_escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '"' => "#quot;".to_string(),
+            '#' => "#35;".to_string(),
+            '<' => "#lt;".to_string(),
+            '>' => "#gt;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// The call graph as a Mermaid flowchart.
+pub fn mermaid(records: &Records, filter: &Option<Vec<String>>, options: &Options) -> String {
+    let diagram = Diagram::new(records, filter, options);
+    let mut out = String::new();
+    writeln!(out, "flowchart LR").unwrap();
+    for kind in [
+        UnsafeKind::Function,
+        UnsafeKind::Block,
+        UnsafeKind::Trait,
+        UnsafeKind::Impl,
+    ] {
+        writeln!(
+            out,
+            "    classDef {} fill:{}",
+            mermaid_class(&kind),
+            color(&kind)
+        )
+        .unwrap();
+    }
+
+    for (k, (cluster, nodes)) in diagram.clusters().into_iter().enumerate() {
+        let indent = if cluster.is_some() {
+            "        "
+        } else {
+            "    "
+        };
+        if let Some(cluster) = cluster {
+            writeln!(out, "    subgraph c{} [\"{}\"]", k, mermaid_escape(cluster)).unwrap();
+        }
+        for i in nodes {
+            let node = &diagram.nodes[i];
+            let class = match &node.kind {
+                Some(kind) => format!(":::{}", mermaid_class(kind)),
+                None => String::new(),
+            };
+            let label = mermaid_escape(&node.label);
+            writeln!(out, "{}n{}[\"{}\"]{}", indent, i, label, class).unwrap();
+        }
+        if cluster.is_some() {
+            writeln!(out, "    end").unwrap();
+        }
+    }
+
+    // Links are styled by their index, in the order they are declared.
+    let mut styles: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (k, ((caller, callee), kinds)) in diagram.edges.iter().enumerate() {
+        let kind = Diagram::edge_kind(kinds);
+        writeln!(out, "    n{} {} n{}", caller, mermaid_arrow(kind), callee).unwrap();
+        if let Some(style) = mermaid_link_style(kind) {
+            styles.entry(style).or_default().push(k.to_string());
+        }
+    }
+    for (style, links) in styles {
+        writeln!(out, "    linkStyle {} {}", links.join(","), style).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_of_paths() {
+        assert_eq!(
+            segments("vmsa::page_table::map"),
+            ["vmsa", "page_table", "map"]
+        );
+        assert_eq!(
+            segments("vmsa::<impl page_table::PageTable<A, L, E, N>>::new_in"),
+            ["vmsa", "<impl page_table::PageTable<A, L, E, N>>", "new_in"]
+        );
+        assert_eq!(
+            segments("<alloc::vec::Vec<T> as core::ops::Drop>::drop"),
+            ["<alloc::vec::Vec<T> as core::ops::Drop>", "drop"]
+        );
+        assert_eq!(segments("toy::f::{closure#0}"), ["toy", "f", "{closure#0}"]);
+        assert_eq!(segments("fn(u32) -> u32"), ["fn(u32) -> u32"]);
+    }
+
+    #[test]
+    fn modules_of_paths() {
+        assert_eq!(modules("uart::pl011::DeviceInner::putc"), ["uart", "pl011"]);
+        assert_eq!(modules("uart::pl011::init"), ["uart", "pl011"]);
+        assert_eq!(
+            modules("<alloc::vec::Vec<T> as core::ops::Drop>::drop"),
+            ["alloc", "vec"]
+        );
+        assert!(modules("fn(u32) -> u32").is_empty());
+    }
+
+    #[test]
+    fn modules_of_closures() {
+        assert_eq!(modules("toy::f::{closure#0}"), ["toy"]);
+        assert_eq!(
+            modules("toy::inner::f::{closure#0}::{closure#0}"),
+            ["toy", "inner"]
+        );
+        assert_eq!(modules("toy::inner::f::{constant#0}"), ["toy", "inner"]);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            mermaid_escape("<Vec<T> as Drop>::drop \"#\""),
+            "#lt;Vec#lt;T#gt; as Drop#gt;::drop #quot;#35;#quot;"
+        );
+        assert_eq!(dot_escape(r#"a "b" \c"#), r#"a \"b\" \\c"#);
+    }
+}
@@ -0,0 +1,154 @@
+//! The records as a single HTML file, which is browsed offline.
+//!
+//! The page embeds the `summary` and `list` reports of `json`, the source of every unsafe span
+//! and the whole call graph, whose caller and callee trees are only built when they are opened.
+
+use crate::json;
+use crate::record::SourceSpan;
+use crate::records::Records;
+use crate::report::selected;
+
+use serde::Serialize;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const TEMPLATE: &str = include_str!("html/report.html");
+
+// The lines shown around a span, and the most lines of a span which are shown
+const CONTEXT: usize = 2;
+const MAX_LINES: usize = 30;
+
+#[derive(Serialize)]
+struct Data<'a> {
+    summary: json::Report<json::Summary<'a>>,
+    list: json::Report<json::List<'a>>,
+    // `file:line` of a span -> its source
+    snippets: BTreeMap<String, Snippet>,
+    // [id, name, unsafe]
+    nodes: Vec<(&'a str, &'a str, bool)>,
+    // per node, [callee, description] of its calls
+    callees: Vec<Vec<(usize, String)>>,
+}
+
+#[derive(Serialize)]
+struct Snippet {
+    // the number of the first line
+    start: usize,
+    lines: Vec<String>,
+}
+
+// Relative paths of the spans are relative to `source_root`, i.e. the workspace root.
+struct Sources<'a> {
+    source_root: Option<&'a Path>,
+    files: BTreeMap<String, Option<Vec<String>>>,
+}
+
+impl Sources<'_> {
+    fn snippet(&mut self, span: &SourceSpan) -> Option<Snippet> {
+        let source_root = self.source_root;
+        let lines = self
+            .files
+            .entry(span.file.clone())
+            .or_insert_with(|| {
+                let path = match source_root {
+                    Some(root) => root.join(&span.file),
+                    None => PathBuf::from(&span.file),
+                };
+                let text = fs::read_to_string(path).ok()?;
+                Some(text.lines().map(str::to_string).collect())
+            })
+            .as_ref()?;
+
+        let start = span.line.saturating_sub(CONTEXT).max(1);
+        let end = (span.end_line + CONTEXT)
+            .min(span.line + MAX_LINES)
+            .min(lines.len());
+        (start <= end).then(|| Snippet {
+            start,
+            lines: lines[start - 1..end].to_vec(),
+        })
+    }
+}
+
+/// The page of the records of the crates in `filter`, or all of them.
+///
+/// The call graph always spans every crate, so that calls into dependencies can be followed.
+pub fn report(
+    records: &Records,
+    filter: &Option<Vec<String>>,
+    apis: &[String],
+    source_root: Option<&Path>,
+) -> String {
+    let mut sources = Sources {
+        source_root,
+        files: BTreeMap::new(),
+    };
+    let mut snippets = BTreeMap::new();
+    for record in selected(records, filter) {
+        let spans = records
+            .own_items(record)
+            .map(|item| &item.span)
+            .chain(
+                record
+                    .findings
+                    .values()
+                    .flatten()
+                    .map(|finding| &finding.span),
+            )
+            .chain(record.diagnostics.iter().map(|diagnostic| &diagnostic.span));
+        for span in spans {
+            let key = format!("{}:{}", span.file, span.line);
+            if let Entry::Vacant(entry) = snippets.entry(key) {
+                if let Some(snippet) = sources.snippet(span) {
+                    entry.insert(snippet);
+                }
+            }
+        }
+    }
+
+    // Every node of the call graph, including the functions and callees without a name
+    let mut index: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut nodes = Vec::new();
+    let ids = records
+        .nodes()
+        .map(|(id, _)| id)
+        .chain(records.functions())
+        .chain(
+            records
+                .functions()
+                .flat_map(|id| records.callees(id))
+                .map(|edge| &edge.callee),
+        );
+    for id in ids {
+        index.entry(id).or_insert_with(|| {
+            nodes.push((id.as_str(), records.name(id), records.is_unsafe(id)));
+            nodes.len() - 1
+        });
+    }
+    let callees = nodes
+        .iter()
+        .map(|(id, _, _)| {
+            records
+                .callees(id)
+                .iter()
+                .map(|edge| (index[edge.callee.as_str()], edge.describe()))
+                .collect()
+        })
+        .collect();
+
+    let data = Data {
+        summary: json::summary(records, filter),
+        list: json::unsafe_list(records, filter, apis),
+        snippets,
+        nodes,
+        callees,
+    };
+    // `</script>` in a name or a source line must not end the script of the page,
+    // `<` only occurs in the strings of the json.
+    let data = serde_json::to_string(&data)
+        .expect("Failed to serialize the report.")
+        .replace('<', "\\u003c");
+    TEMPLATE.replace("/*DATA*/", &data)
+}
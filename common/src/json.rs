@@ -0,0 +1,556 @@
+//! The reports of `report` as JSON, for CI and other tools.
+//!
+//! Every report is an object with the `version` of its layout and the name of the `report`,
+//! the layouts are documented in `doc/report-schema.md`.
+
+pub use crate::check::Violation;
+use crate::record::{
+    CallKind, CallSite, CrateKind, DiagnosticKind, FindingKind, NodeId, OwnerKind, Record,
+    SourceSpan, TargetKind, UnsafeKind,
+};
+use crate::records::Records;
+use crate::report::{api_calls, functions_of, items_by_crate, selected};
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// Bumped whenever the layout of a report changes
+pub const REPORT_VERSION: u32 = 3;
+
+#[derive(Serialize, Debug)]
+pub struct Report<T> {
+    pub version: u32,
+    pub report: &'static str,
+    #[serde(flatten)]
+    pub body: T,
+}
+
+impl<T: Serialize> Report<T> {
+    pub fn new(report: &'static str, body: T) -> Self {
+        Self {
+            version: REPORT_VERSION,
+            report,
+            body,
+        }
+    }
+
+    /// Prints the report to stdout.
+    pub fn print(&self) {
+        let json = serde_json::to_string_pretty(self).expect("Failed to serialize the report.");
+        println!("{}", json);
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Summary<'a> {
+    pub crates: Vec<CrateSummary<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CrateSummary<'a> {
+    #[serde(rename = "crate")]
+    pub krate: &'a str,
+    pub version: &'a str,
+    pub target: &'a TargetKind,
+    pub crate_kind: &'a CrateKind,
+    pub target_triple: &'a str,
+    pub functions: usize,
+    pub blocks: usize,
+    pub impls: usize,
+    pub traits: usize,
+    // uninitialized memory findings
+    pub findings: usize,
+    // analysis gaps
+    pub diagnostics: usize,
+}
+
+/// The unsafe items of each crate, counted by kind.
+pub fn summary<'a>(records: &'a Records, filter: &'a Option<Vec<String>>) -> Report<Summary<'a>> {
+    let crates = selected(records, filter)
+        .map(|record| {
+            let count = |kind| {
+                records
+                    .own_items(record)
+                    .filter(|item| item.kind == kind)
+                    .count()
+            };
+            CrateSummary {
+                krate: &record.krate,
+                version: &record.package.version,
+                target: &record.target,
+                crate_kind: &record.crate_kind,
+                target_triple: &record.target_triple,
+                functions: count(UnsafeKind::Function),
+                blocks: count(UnsafeKind::Block),
+                impls: count(UnsafeKind::Impl),
+                traits: count(UnsafeKind::Trait),
+                findings: record.findings.values().map(Vec::len).sum(),
+                diagnostics: record.diagnostics.len(),
+            }
+        })
+        .collect();
+
+    Report::new("summary", Summary { crates })
+}
+
+#[derive(Serialize, Debug)]
+pub struct List<'a> {
+    pub crates: Vec<CrateList<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CrateList<'a> {
+    #[serde(rename = "crate")]
+    pub krate: &'a str,
+    pub version: &'a str,
+    pub target: &'a TargetKind,
+    pub items: Vec<Item<'a>>,
+    pub unsafe_drops: Vec<UnsafeDrop<'a>>,
+    pub findings: Vec<Located<'a, FindingKind>>,
+    pub diagnostics: Vec<Located<'a, DiagnosticKind>>,
+    pub dangerous_api_calls: Vec<ApiCall<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Item<'a> {
+    pub kind: &'a UnsafeKind,
+    pub id: &'a str,
+    pub name: &'a str,
+    pub owner: &'a OwnerKind,
+    pub span: &'a SourceSpan,
+    // `compile-time` or `runtime-reachable` in const contexts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub evaluation: Option<&'static str>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct UnsafeDrop<'a> {
+    #[serde(rename = "type")]
+    pub ty: &'a str,
+    pub drop: &'a str,
+}
+
+// A finding or a diagnostic, in the function `owner`
+#[derive(Serialize, Debug)]
+pub struct Located<'a, K> {
+    pub kind: &'a K,
+    pub owner: &'a str,
+    pub span: &'a SourceSpan,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ApiCall<'a> {
+    pub api: &'a str,
+    pub caller: &'a str,
+    pub kind: &'a CallKind,
+    pub sites: &'a [CallSite],
+}
+
+/// The unsafe items of each crate, with their findings, analysis gaps and calls to `apis`.
+pub fn unsafe_list<'a>(
+    records: &'a Records,
+    filter: &'a Option<Vec<String>>,
+    apis: &'a [String],
+) -> Report<List<'a>> {
+    let crates = selected(records, filter)
+        .map(|record| CrateList {
+            krate: &record.krate,
+            version: &record.package.version,
+            target: &record.target,
+            items: items(records, record),
+            unsafe_drops: records
+                .unsafe_drops(record)
+                .map(|(ty, drop_fn)| UnsafeDrop {
+                    ty,
+                    drop: records.name(drop_fn),
+                })
+                .collect(),
+            findings: record
+                .findings
+                .iter()
+                .flat_map(|(owner, findings)| {
+                    findings.iter().map(move |finding| Located {
+                        kind: &finding.kind,
+                        owner: records.name(owner),
+                        span: &finding.span,
+                    })
+                })
+                .collect(),
+            diagnostics: record
+                .diagnostics
+                .iter()
+                .map(|diagnostic| Located {
+                    kind: &diagnostic.kind,
+                    owner: records.name(&diagnostic.owner),
+                    span: &diagnostic.span,
+                })
+                .collect(),
+            dangerous_api_calls: api_calls(records, record, apis)
+                .map(|(caller, edge)| ApiCall {
+                    api: records.name(&edge.callee),
+                    caller: records.name(caller),
+                    kind: &edge.kind,
+                    sites: &edge.sites,
+                })
+                .collect(),
+        })
+        .collect();
+
+    Report::new("list", List { crates })
+}
+
+fn items<'a>(records: &'a Records, record: &'a Record) -> Vec<Item<'a>> {
+    let mut items: Vec<_> = records
+        .own_items(record)
+        .map(|item| Item {
+            kind: &item.kind,
+            id: &item.id,
+            name: &item.name,
+            owner: &item.owner,
+            span: &item.span,
+            evaluation: item.in_const_context.then(|| {
+                if records.is_runtime_reachable(&item.id) {
+                    "runtime-reachable"
+                } else {
+                    "compile-time"
+                }
+            }),
+        })
+        .collect();
+    items.sort_by_key(|item| (item.kind, item.name));
+    items
+}
+
+// A function of the call graph, with its callees in `trace` and its callers in `callers`
+#[derive(Serialize, Debug)]
+pub struct Node<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    #[serde(rename = "unsafe")]
+    pub is_unsafe: bool,
+    // `None` for the roots
+    #[serde(flatten)]
+    pub call: Option<Call<'a>>,
+    pub children: Vec<Node<'a>>,
+}
+
+// The calls between a node and its parent
+#[derive(Serialize, Debug)]
+pub struct Call<'a> {
+    pub kind: &'a CallKind,
+    pub sites: &'a [CallSite],
+    // the child is already on the path from the root, its children are left out
+    pub recursive: bool,
+    // the child is already expanded elsewhere in the tree, its children are left out
+    pub seen: bool,
+}
+
+impl<'a> Node<'a> {
+    fn new(records: &'a Records, id: &'a str) -> Self {
+        Self {
+            id,
+            name: records.name(id),
+            is_unsafe: records.is_unsafe(id),
+            call: None,
+            children: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Trace<'a> {
+    pub crates: Vec<CrateTrace<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CrateTrace<'a> {
+    #[serde(rename = "crate")]
+    pub krate: &'a str,
+    pub target: &'a TargetKind,
+    pub functions: Vec<Node<'a>>,
+}
+
+/// The call trees of the functions of each record, callees deeper than `depth` are left out.
+pub fn call_trace<'a>(
+    records: &'a Records,
+    selected: impl IntoIterator<Item = &'a Record>,
+    depth: Option<usize>,
+) -> Report<Trace<'a>> {
+    let crates = selected
+        .into_iter()
+        .map(|record| {
+            let functions = functions_of(records, record)
+                .into_iter()
+                .map(|caller| {
+                    let mut node = Node::new(records, caller);
+                    let mut tree = Tree::new(caller);
+                    node.children = callees(records, &mut tree, 1, depth.unwrap_or(usize::MAX));
+                    node
+                })
+                .collect();
+            CrateTrace {
+                krate: &record.krate,
+                target: &record.target,
+                functions,
+            }
+        })
+        .collect();
+
+    Report::new("trace", Trace { crates })
+}
+
+// The tree being built from a root, like in `report::visit_callee`.
+// Every node is expanded once, so that shared callees do not multiply the tree.
+struct Tree<'a> {
+    // the path from the root
+    stack: Vec<&'a str>,
+    expanded: BTreeSet<&'a str>,
+}
+
+impl<'a> Tree<'a> {
+    fn new(root: &'a str) -> Self {
+        Self {
+            stack: vec![root],
+            expanded: BTreeSet::from([root]),
+        }
+    }
+
+    fn last(&self) -> &'a str {
+        self.stack[self.stack.len() - 1]
+    }
+
+    // (recursive, seen) of a child of the last node
+    fn visit(&mut self, id: &'a str) -> (bool, bool) {
+        let recursive = self.stack.contains(&id);
+        let seen = !recursive && !self.expanded.insert(id);
+        (recursive, seen)
+    }
+}
+
+fn callees<'a>(
+    records: &'a Records,
+    tree: &mut Tree<'a>,
+    depth: usize,
+    max_depth: usize,
+) -> Vec<Node<'a>> {
+    if depth > max_depth {
+        return Vec::new();
+    }
+
+    let caller = tree.last();
+    records
+        .callees(caller)
+        .iter()
+        .map(|edge| {
+            let callee = edge.callee.as_str();
+            let (recursive, seen) = tree.visit(callee);
+            let mut node = Node::new(records, callee);
+            if !recursive && !seen {
+                tree.stack.push(callee);
+                node.children = callees(records, tree, depth + 1, max_depth);
+                tree.stack.pop();
+            }
+            node.call = Some(Call {
+                kind: &edge.kind,
+                sites: &edge.sites,
+                recursive,
+                seen,
+            });
+            node
+        })
+        .collect()
+}
+
+#[derive(Serialize, Debug)]
+pub struct Callers<'a> {
+    pub functions: Vec<Node<'a>>,
+}
+
+/// The caller trees of `ids`, up to `depth` callers.
+pub fn callers<'a>(records: &'a Records, ids: &[&'a NodeId], depth: usize) -> Report<Callers<'a>> {
+    let functions = ids
+        .iter()
+        .map(|id| {
+            let mut node = Node::new(records, id);
+            node.children = callers_of(records, &mut Tree::new(id), 1, depth);
+            node
+        })
+        .collect();
+
+    Report::new("callers", Callers { functions })
+}
+
+fn callers_of<'a>(
+    records: &'a Records,
+    tree: &mut Tree<'a>,
+    depth: usize,
+    max_depth: usize,
+) -> Vec<Node<'a>> {
+    if depth > max_depth {
+        return Vec::new();
+    }
+
+    let callee = tree.last();
+    let mut callers: Vec<_> = records.callers(callee).collect();
+    callers.sort_by_key(|(caller, _)| records.name(caller));
+
+    callers
+        .into_iter()
+        .map(|(caller, edge)| {
+            let (recursive, seen) = tree.visit(caller);
+            let mut node = Node::new(records, caller);
+            if !recursive && !seen {
+                tree.stack.push(caller);
+                node.children = callers_of(records, tree, depth + 1, max_depth);
+                tree.stack.pop();
+            }
+            node.call = Some(Call {
+                kind: &edge.kind,
+                sites: &edge.sites,
+                recursive,
+                seen,
+            });
+            node
+        })
+        .collect()
+}
+
+#[derive(Serialize, Debug)]
+pub struct Diff<'a> {
+    pub crates: Vec<CrateDiff<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CrateDiff<'a> {
+    #[serde(rename = "crate")]
+    pub krate: &'a str,
+    pub added: Vec<DiffItem<'a>>,
+    pub removed: Vec<DiffItem<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DiffItem<'a> {
+    pub kind: UnsafeKind,
+    pub name: &'a str,
+}
+
+/// The unsafe items added and removed since `baseline`, of the crates which changed.
+pub fn diff<'a>(
+    baseline: &'a Records,
+    records: &'a Records,
+    filter: &'a Option<Vec<String>>,
+) -> Report<Diff<'a>> {
+    let old = items_by_crate(baseline, filter);
+    let new = items_by_crate(records, filter);
+    let krates: BTreeSet<_> = old.keys().chain(new.keys()).copied().collect();
+
+    let empty = BTreeSet::new();
+    let list = |items: BTreeSet<&(UnsafeKind, &'a str)>| {
+        items
+            .into_iter()
+            .map(|(kind, name)| DiffItem {
+                kind: kind.clone(),
+                name,
+            })
+            .collect()
+    };
+    let crates = krates
+        .into_iter()
+        .filter_map(|krate| {
+            let old = old.get(krate).unwrap_or(&empty);
+            let new = new.get(krate).unwrap_or(&empty);
+            (old != new).then(|| CrateDiff {
+                krate,
+                added: list(new.difference(old).collect()),
+                removed: list(old.difference(new).collect()),
+            })
+        })
+        .collect();
+
+    Report::new("diff", Diff { crates })
+}
+
+#[derive(Serialize, Debug)]
+pub struct Check {
+    pub passed: bool,
+    pub violations: Vec<Violation>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::{CrateKind, Fingerprint, PackageId, SourceSpan};
+    use serde_json::{json, Value};
+
+    // `f` calls `a` and `b`, which both call `c`, which calls `d`.
+    fn diamond() -> Records {
+        let package = PackageId {
+            name: "toy".to_string(),
+            version: "0.1.0".to_string(),
+            source: "path+/toy".to_string(),
+        };
+        let mut record = Record::new(
+            "toy".to_string(),
+            package,
+            1,
+            TargetKind::Lib,
+            CrateKind::Target,
+            "x86_64-unknown-linux-gnu".to_string(),
+            Fingerprint::default(),
+        );
+        let site = CallSite {
+            span: SourceSpan {
+                file: "src/lib.rs".to_string(),
+                line: 1,
+                col: 1,
+                end_line: 1,
+                end_col: 1,
+            },
+            in_unsafe: false,
+        };
+        for id in ["f", "a", "b", "c", "d"] {
+            record.add_node(id.to_string(), format!("toy::{}", id));
+        }
+        for (caller, callee) in [("f", "a"), ("f", "b"), ("a", "c"), ("b", "c"), ("c", "d")] {
+            let (caller, callee) = (caller.to_string(), callee.to_string());
+            record.add_edge(caller, callee, CallKind::Direct, site.clone());
+        }
+        Records::new(vec![record], Vec::new())
+    }
+
+    // (name, seen) of every node under `node`, depth first
+    fn flatten(node: &Value, nodes: &mut Vec<(String, bool)>) {
+        for child in node["children"].as_array().unwrap() {
+            let name = child["name"].as_str().unwrap().to_string();
+            nodes.push((name, child["seen"] == json!(true)));
+            flatten(child, nodes);
+        }
+    }
+
+    #[test]
+    fn shared_callees_are_expanded_once() {
+        let records = diamond();
+        let trace = serde_json::to_value(call_trace(&records, records.crates(), None)).unwrap();
+        let root = trace["crates"][0]["functions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|node| node["name"] == "toy::f")
+            .unwrap();
+
+        let mut nodes = Vec::new();
+        flatten(root, &mut nodes);
+        let expected = [
+            ("toy::a", false),
+            ("toy::c", false),
+            ("toy::d", false),
+            ("toy::b", false),
+            ("toy::c", true),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(name, seen)| (name.to_string(), *seen))
+            .collect();
+        assert_eq!(nodes, expected);
+    }
+}
@@ -0,0 +1,621 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Hex encoded `DefPathHash` of an item, the same in every crate referring to it
+pub type NodeId = String;
+
+/// 64-bit FNV-1a, stable across releases unlike the hashers of std.
+pub fn fnv1a(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone, Ord, PartialOrd)]
+pub enum UnsafeKind {
+    Function,
+    Block,
+    Trait,
+    Impl,
+}
+
+// The item owning unsafe code
+#[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone, Ord, PartialOrd)]
+pub enum OwnerKind {
+    Fn,
+    ConstFn,
+    Const,
+    Static,
+    // e.g. array lengths and enum discriminants
+    AnonConst,
+    InlineConst,
+    Trait,
+    Impl,
+}
+
+impl OwnerKind {
+    pub fn is_const(&self) -> bool {
+        matches!(
+            self,
+            OwnerKind::ConstFn
+                | OwnerKind::Const
+                | OwnerKind::Static
+                | OwnerKind::AnonConst
+                | OwnerKind::InlineConst
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, Clone)]
+pub struct UnsafeItem {
+    pub kind: UnsafeKind,
+    pub id: NodeId,
+    pub name: String,
+    pub owner: OwnerKind,
+    pub in_const_context: bool,
+    // the first unsafe block of the owner, or the declaration
+    pub span: SourceSpan,
+}
+
+impl UnsafeItem {
+    pub fn new(
+        kind: UnsafeKind,
+        id: NodeId,
+        name: String,
+        owner: OwnerKind,
+        span: SourceSpan,
+    ) -> Self {
+        let in_const_context = owner.is_const();
+        Self {
+            kind,
+            id,
+            name,
+            owner,
+            in_const_context,
+            span,
+        }
+    }
+}
+
+impl Ord for UnsafeItem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+impl PartialOrd for UnsafeItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for UnsafeItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+// An unsafe block, justified by a `// SAFETY:` comment right above it
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Ord, PartialOrd)]
+pub struct UnsafeBlock {
+    // the function, const or static containing the block
+    pub owner: NodeId,
+    pub span: SourceSpan,
+    pub justified: bool,
+    // `fnv1a` of the source of the block without whitespace, which only changes with the block
+    pub digest: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone, Ord, PartialOrd)]
+pub struct SourceSpan {
+    pub file: String,
+    pub line: usize,
+    pub col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+impl fmt::Display for SourceSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone, Ord, PartialOrd)]
+pub enum CallKind {
+    // functions and constructors
+    Direct,
+    // inherent methods
+    Method,
+    // trait methods, resolved statically or through `dyn`
+    TraitDispatch,
+    FnPointer,
+    // `Drop::drop` run by drop glue
+    Drop,
+    Closure,
+}
+
+impl fmt::Display for CallKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallKind::Direct => write!(f, "direct"),
+            CallKind::Method => write!(f, "method"),
+            CallKind::TraitDispatch => write!(f, "trait-dispatch"),
+            CallKind::FnPointer => write!(f, "fn-pointer"),
+            CallKind::Drop => write!(f, "drop"),
+            CallKind::Closure => write!(f, "closure"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Ord, PartialOrd)]
+pub struct CallSite {
+    pub span: SourceSpan,
+    pub in_unsafe: bool,
+}
+
+/// All calls from a caller to the same callee with the same kind.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct Edge {
+    pub callee: NodeId,
+    pub kind: CallKind,
+    pub sites: Vec<CallSite>,
+}
+
+impl Edge {
+    pub fn count(&self) -> usize {
+        self.sites.len()
+    }
+
+    // e.g. `called 2× at uart/src/pl011.rs:88,91 inside unsafe`
+    pub fn describe(&self) -> String {
+        let mut desc = String::new();
+        if self.kind != CallKind::Direct && self.kind != CallKind::Method {
+            desc.push_str(&format!("[{}] ", self.kind));
+        }
+
+        if self.count() > 1 {
+            desc.push_str(&format!("called {}× at ", self.count()));
+        } else {
+            desc.push_str("called at ");
+        }
+
+        let mut files: Vec<(&str, Vec<String>)> = Vec::new();
+        for site in &self.sites {
+            let line = site.span.line.to_string();
+            match files.iter_mut().find(|(file, _)| *file == site.span.file) {
+                Some((_, lines)) => lines.push(line),
+                None => files.push((&site.span.file, vec![line])),
+            }
+        }
+        let locations: Vec<_> = files
+            .iter()
+            .map(|(file, lines)| format!("{}:{}", file, lines.join(",")))
+            .collect();
+        desc.push_str(&locations.join(", "));
+
+        let in_unsafe = self.sites.iter().filter(|site| site.in_unsafe).count();
+        if in_unsafe > 0 && in_unsafe == self.count() {
+            desc.push_str(" inside unsafe");
+        } else if in_unsafe > 0 {
+            desc.push_str(&format!(" ({}× inside unsafe)", in_unsafe));
+        }
+
+        desc
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone, Ord, PartialOrd)]
+pub enum FindingKind {
+    // `MaybeUninit::uninit().assume_init()`
+    AssumeInitUninit,
+    // `mem::uninitialized()`
+    MemUninitialized,
+    // `mem::zeroed::<T>()` where all-zero is not a valid `T`
+    MemZeroed { ty: String },
+    // `Vec::with_capacity` followed by `set_len`
+    VecSetLen,
+}
+
+impl fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FindingKind::AssumeInitUninit => write!(f, "MaybeUninit::uninit().assume_init()"),
+            FindingKind::MemUninitialized => write!(f, "mem::uninitialized()"),
+            FindingKind::MemZeroed { ty } => write!(f, "mem::zeroed::<{}>()", ty),
+            FindingKind::VecSetLen => write!(f, "Vec::with_capacity() + set_len()"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Ord, PartialOrd)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub span: SourceSpan,
+}
+
+// A part of a crate the analysis could not follow, i.e. a gap in the call graph
+#[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone, Ord, PartialOrd)]
+pub enum DiagnosticKind {
+    // a call whose callee has no resolution in the typeck results
+    UnresolvedCall,
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticKind::UnresolvedCall => write!(f, "unresolved call"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Ord, PartialOrd)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    // the enclosing function
+    pub owner: NodeId,
+    pub span: SourceSpan,
+}
+
+// The cargo target a crate was compiled for
+#[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone, Ord, PartialOrd)]
+pub enum TargetKind {
+    Lib,
+    Bin,
+    Test,
+    Bench,
+    Example,
+    BuildScript,
+}
+
+impl TargetKind {
+    pub fn is_production(&self) -> bool {
+        matches!(self, TargetKind::Lib | TargetKind::Bin)
+    }
+}
+
+impl fmt::Display for TargetKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetKind::Lib => write!(f, "lib"),
+            TargetKind::Bin => write!(f, "bin"),
+            TargetKind::Test => write!(f, "test"),
+            TargetKind::Bench => write!(f, "bench"),
+            TargetKind::Example => write!(f, "example"),
+            TargetKind::BuildScript => write!(f, "build-script"),
+        }
+    }
+}
+
+// Whether a crate runs on the host during the build or ends up in the built artifact
+#[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone, Ord, PartialOrd)]
+pub enum CrateKind {
+    ProcMacro,
+    // build scripts and their dependencies
+    BuildDependency,
+    // dependencies only linked into the tests, benches and examples of the workspace
+    DevDependency,
+    Target,
+}
+
+impl fmt::Display for CrateKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CrateKind::ProcMacro => write!(f, "proc-macro"),
+            CrateKind::BuildDependency => write!(f, "build-dep"),
+            CrateKind::DevDependency => write!(f, "dev-dep"),
+            CrateKind::Target => write!(f, "target"),
+        }
+    }
+}
+
+// Identifies a package like cargo does, so that several versions of a crate can coexist.
+#[derive(Serialize, Deserialize, Debug, Hash, Eq, PartialEq, Clone, Ord, PartialOrd)]
+pub struct PackageId {
+    pub name: String,
+    pub version: String,
+    // e.g. `registry+index.crates.io-6f17d22bba15001f`, `git+...`, `path+/home/user/project`
+    pub source: String,
+}
+
+impl fmt::Display for PackageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{} ({})", self.name, self.version, self.source)
+    }
+}
+
+// Identifies the compilation a record was made from, so that unchanged crates are not re-analysed.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone)]
+pub struct Fingerprint {
+    // hash of the `-C extra-filename` given by cargo, which names the artifacts of the compilation
+    pub unit: String,
+    pub features: Vec<String>,
+    // rustc arguments, sources of the crate and crate hashes of its dependencies
+    pub hash: String,
+}
+
+// Bumped whenever the layout of `Record` changes
+pub const SCHEMA_VERSION: u32 = 11;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Header {
+    pub schema_version: u32,
+    pub tool_version: String,
+    pub rustc_version: String,
+    // seconds since the unix epoch
+    pub timestamp: u64,
+}
+
+impl Header {
+    pub fn new() -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        Self {
+            schema_version: SCHEMA_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            rustc_version: env!("UTRACE_RUSTC_VERSION").to_string(),
+            timestamp,
+        }
+    }
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct SchemaMismatch {
+    pub schema_version: u32,
+    pub tool_version: String,
+}
+
+impl fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "written with schema version {} by utrace {}, but utrace {} expects schema version {}. \
+             Re-run the analysis to regenerate the records.",
+            self.schema_version,
+            self.tool_version,
+            env!("CARGO_PKG_VERSION"),
+            SCHEMA_VERSION
+        )
+    }
+}
+
+impl std::error::Error for SchemaMismatch {}
+
+/// The unsafe items, call graph and findings of one compilation of a crate.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Record {
+    pub header: Header,
+    pub krate: String,
+    pub package: PackageId,
+    // Differs for every compilation of the crate (version, source, features, target kind...)
+    pub stable_crate_id: u64,
+    pub target: TargetKind,
+    pub crate_kind: CrateKind,
+    pub target_triple: String,
+    pub fingerprint: Fingerprint,
+    // id -> human-readable path of every node referred to by this record
+    pub nodes: BTreeMap<NodeId, String>,
+    // id -> path of the module defining the node, e.g. of the closures of a function
+    pub modules: BTreeMap<NodeId, String>,
+    pub items: BTreeSet<UnsafeItem>,
+    // every unsafe block, while `items` has one per owner
+    pub blocks: Vec<UnsafeBlock>,
+    pub graph: BTreeMap<NodeId, Vec<Edge>>,
+    // per enclosing function
+    pub findings: BTreeMap<NodeId, Vec<Finding>>,
+    // type -> `Drop::drop` impl
+    pub drops: BTreeMap<String, NodeId>,
+    // consts, statics and const fns
+    pub const_contexts: BTreeMap<NodeId, OwnerKind>,
+    // const fns other crates can call, i.e. exported by a library
+    pub exported: BTreeSet<NodeId>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Record {
+    pub fn new(
+        krate: String,
+        package: PackageId,
+        stable_crate_id: u64,
+        target: TargetKind,
+        crate_kind: CrateKind,
+        target_triple: String,
+        fingerprint: Fingerprint,
+    ) -> Self {
+        Self {
+            header: Header::new(),
+            krate,
+            package,
+            stable_crate_id,
+            target,
+            crate_kind,
+            target_triple,
+            fingerprint,
+            nodes: BTreeMap::new(),
+            modules: BTreeMap::new(),
+            items: BTreeSet::new(),
+            blocks: Vec::new(),
+            graph: BTreeMap::new(),
+            findings: BTreeMap::new(),
+            drops: BTreeMap::new(),
+            const_contexts: BTreeMap::new(),
+            exported: BTreeSet::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn add_node(&mut self, id: NodeId, name: String) {
+        self.nodes.entry(id).or_insert(name);
+    }
+
+    pub fn name<'a>(&'a self, id: &'a str) -> &'a str {
+        self.nodes.get(id).map_or(id, String::as_str)
+    }
+
+    pub fn add_module(&mut self, id: NodeId, module: String) {
+        self.modules.entry(id).or_insert(module);
+    }
+
+    pub fn add_item(&mut self, kind: UnsafeKind, id: NodeId, owner: OwnerKind, span: SourceSpan) {
+        let name = self.name(&id).to_string();
+        self.items
+            .insert(UnsafeItem::new(kind, id, name, owner, span));
+    }
+
+    pub fn add_block(&mut self, owner: NodeId, span: SourceSpan, justified: bool, digest: String) {
+        self.blocks.push(UnsafeBlock {
+            owner,
+            span,
+            justified,
+            digest,
+        });
+    }
+
+    pub fn add_const_context(&mut self, id: NodeId, kind: OwnerKind) {
+        self.const_contexts.insert(id, kind);
+    }
+
+    pub fn add_exported(&mut self, id: NodeId) {
+        self.exported.insert(id);
+    }
+
+    // Functions without any callee are nodes of the call graph, too.
+    pub fn add_fn(&mut self, id: NodeId) {
+        self.graph.entry(id).or_default();
+    }
+
+    pub fn add_edge(&mut self, caller: NodeId, callee: NodeId, kind: CallKind, site: CallSite) {
+        let edges = self.graph.entry(caller).or_default();
+        match edges
+            .iter_mut()
+            .find(|edge| edge.callee == callee && edge.kind == kind)
+        {
+            Some(edge) => edge.sites.push(site),
+            None => edges.push(Edge {
+                callee,
+                kind,
+                sites: vec![site],
+            }),
+        }
+    }
+
+    pub fn add_finding(&mut self, owner: NodeId, kind: FindingKind, span: SourceSpan) {
+        self.findings
+            .entry(owner)
+            .or_default()
+            .push(Finding { kind, span });
+    }
+
+    pub fn add_diagnostic(&mut self, kind: DiagnosticKind, owner: NodeId, span: SourceSpan) {
+        self.diagnostics.push(Diagnostic { kind, owner, span });
+    }
+
+    pub fn add_drop_impl(&mut self, ty: String, drop_fn: NodeId) {
+        self.drops.insert(ty, drop_fn);
+    }
+
+    // The same crate can be compiled several times: other versions, its unit tests,
+    // or once for build scripts and once for the target.
+    pub fn path(&self, dir: &str) -> String {
+        format!(
+            "{}/{}-{}-{:016x}.record",
+            dir, self.krate, self.package.version, self.stable_crate_id
+        )
+    }
+
+    pub fn save(&self, dir: &str) -> io::Result<()> {
+        let path = self.path(dir);
+
+        // Parallel rustc invocations share the directory, so a record is written to
+        // a temporary file first and renamed into place once it is complete.
+        let tmp_path = format!("{}.tmp-{}", path, std::process::id());
+        let write = || -> io::Result<()> {
+            let file = File::create(&tmp_path)?;
+            let mut writer = BufWriter::new(file);
+            serde_json::to_writer(&mut writer, self)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()
+        };
+        // A partial file would be left behind until the next prune otherwise.
+        if let Err(e) = write().and_then(|()| fs::rename(&tmp_path, path)) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let value: serde_json::Value = serde_json::from_reader(reader)?;
+
+        // Records written before the header was introduced are version 0.
+        let header = &value["header"];
+        let schema_version = header["schema_version"].as_u64().unwrap_or(0) as u32;
+        if schema_version != SCHEMA_VERSION {
+            let tool_version = header["tool_version"].as_str().unwrap_or("unknown");
+            let mismatch = SchemaMismatch {
+                schema_version,
+                tool_version: tool_version.to_string(),
+            };
+            return Err(io::Error::new(io::ErrorKind::InvalidData, mismatch));
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_save_leaves_no_file() {
+        let dir = std::env::temp_dir().join(format!("utrace-record-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.display().to_string();
+
+        let package = PackageId {
+            name: "toy".to_string(),
+            version: "0.1.0".to_string(),
+            source: "path+/tmp/toy".to_string(),
+        };
+        let record = Record::new(
+            "toy".to_string(),
+            package,
+            1,
+            TargetKind::Lib,
+            CrateKind::Target,
+            "x86_64-unknown-linux-gnu".to_string(),
+            Fingerprint::default(),
+        );
+        // The record cannot be renamed over a directory which is not empty.
+        fs::create_dir_all(format!("{}/inner", record.path(&dir))).unwrap();
+
+        assert!(record.save(&dir).is_err());
+        let entries: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries.len(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
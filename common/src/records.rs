@@ -0,0 +1,517 @@
+use crate::config;
+use crate::record::{
+    CrateKind, Diagnostic, Edge, Finding, NodeId, OwnerKind, PackageId, Record, SchemaMismatch,
+    UnsafeItem, UnsafeKind,
+};
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The records of every analysed crate, joined into one call graph.
+///
+/// Node ids are shared by all crates, so a call into a dependency can be followed
+/// into the dependency's own record.
+#[derive(Debug)]
+pub struct Records {
+    // per crate
+    raw_data: Vec<Record>,
+
+    // all deps
+    unsafe_list: BTreeSet<NodeId>,
+    call_graph: BTreeMap<NodeId, Vec<Edge>>,
+    // callee -> callers
+    reverse_graph: BTreeMap<NodeId, BTreeSet<NodeId>>,
+    names: BTreeMap<NodeId, String>,
+    modules: BTreeMap<NodeId, String>,
+    // (kind, path) of the items of the libs and bins of each package
+    production: BTreeMap<PackageId, BTreeSet<(UnsafeKind, String)>>,
+
+    // (path, reason) of the files which are not valid records
+    skipped: Vec<(String, String)>,
+}
+
+impl Records {
+    /// Loads the records of the configured output directory.
+    pub fn load() -> io::Result<Self> {
+        Self::load_from(config::out_dir())
+    }
+
+    /// Loads every `.record` file in `dir`.
+    ///
+    /// Unreadable files are skipped and listed by [`Records::skipped`].
+    /// Records written by another schema version are an error.
+    pub fn load_from(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut raw_data = Vec::new();
+        let mut skipped = Vec::new();
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                continue;
+            }
+
+            let name = path.display().to_string();
+            if path.extension().map_or(true, |ext| ext != "record") {
+                skipped.push((name, "not a record".to_string()));
+                continue;
+            }
+
+            match Record::load(&name) {
+                Ok(record) => raw_data.push(record),
+                // Records of another utrace release are not mixed with the current ones.
+                Err(e) if is_schema_mismatch(&e) => {
+                    let msg = format!("{}: {}", name, e);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+                }
+                Err(e) => skipped.push((name, e.to_string())),
+            }
+        }
+
+        Ok(Self::new(raw_data, skipped))
+    }
+
+    /// Joins already loaded records.
+    pub fn new(raw_data: Vec<Record>, skipped: Vec<(String, String)>) -> Self {
+        let unsafe_list = raw_data
+            .iter()
+            .flat_map(|record| record.items.iter().map(|item| item.id.clone()))
+            .collect();
+        let production = production_items(&raw_data);
+
+        let call_graph: BTreeMap<NodeId, Vec<Edge>> = raw_data
+            .iter()
+            .flat_map(|record| record.graph.iter())
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        let mut reverse_graph: BTreeMap<NodeId, BTreeSet<NodeId>> = BTreeMap::new();
+        for (caller, edges) in &call_graph {
+            for edge in edges {
+                reverse_graph
+                    .entry(edge.callee.clone())
+                    .or_default()
+                    .insert(caller.clone());
+            }
+        }
+
+        let names = raw_data
+            .iter()
+            .flat_map(|record| record.nodes.iter())
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        let modules = raw_data
+            .iter()
+            .flat_map(|record| record.modules.iter())
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        Self {
+            raw_data,
+            unsafe_list,
+            call_graph,
+            reverse_graph,
+            names,
+            modules,
+            production,
+            skipped,
+        }
+    }
+
+    /// The files of the directory which could not be loaded, with the reason.
+    pub fn skipped(&self) -> &[(String, String)] {
+        &self.skipped
+    }
+
+    /// Keeps the records for which `f` returns true.
+    pub fn filter(self, f: impl Fn(&Record) -> bool) -> Self {
+        let raw_data = self
+            .raw_data
+            .into_iter()
+            .filter(|record| f(record))
+            .collect();
+        Self::new(raw_data, self.skipped)
+    }
+
+    /// Drops the records of proc-macros, build scripts, dev-dependencies and their dependencies.
+    pub fn runtime_only(self) -> Self {
+        self.filter(|record| record.crate_kind == CrateKind::Target)
+    }
+
+    /// Drops the records of tests, benches, examples and build scripts.
+    pub fn production_only(self) -> Self {
+        self.filter(|record| record.target.is_production())
+    }
+
+    /// One record per compilation of a crate.
+    pub fn crates(&self) -> impl Iterator<Item = &Record> {
+        self.raw_data.iter()
+    }
+
+    /// The records of the crate named `krate`, e.g. its lib and its unit tests.
+    pub fn find_crate<'a>(&'a self, krate: &'a str) -> impl Iterator<Item = &'a Record> {
+        self.crates().filter(move |record| record.krate == krate)
+    }
+
+    /// The unsafe items of every crate.
+    pub fn items(&self) -> impl Iterator<Item = &UnsafeItem> {
+        self.crates().flat_map(|record| record.items.iter())
+    }
+
+    /// The unsafe items of a record which are its own.
+    ///
+    /// The tests, benches and examples of a package are compiled together with its lib or bin,
+    /// so only the items which are not in a lib or bin of the package are theirs,
+    /// i.e. the unsafe code used only by them. The record itself keeps all of its items.
+    pub fn own_items<'a>(&'a self, record: &'a Record) -> impl Iterator<Item = &'a UnsafeItem> {
+        let production = match record.target.is_production() {
+            true => None,
+            false => self.production.get(&record.package),
+        };
+        record.items.iter().filter(move |item| {
+            production.map_or(true, |items| {
+                !items.contains(&(item.kind.clone(), item.name.clone()))
+            })
+        })
+    }
+
+    /// The unsafe items of the given kind, e.g. all unsafe blocks.
+    pub fn items_of_kind(&self, kind: UnsafeKind) -> impl Iterator<Item = &UnsafeItem> {
+        self.items().filter(move |item| item.kind == kind)
+    }
+
+    /// The uninitialized memory findings of every crate, with the enclosing function.
+    pub fn findings(&self) -> impl Iterator<Item = (&NodeId, &Finding)> {
+        self.crates().flat_map(|record| {
+            record
+                .findings
+                .iter()
+                .flat_map(|(owner, findings)| findings.iter().map(move |f| (owner, f)))
+        })
+    }
+
+    /// The parts of every crate the analysis could not follow, e.g. unresolved calls.
+    pub fn diagnostics(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.crates().flat_map(|record| record.diagnostics.iter())
+    }
+
+    /// Every known node with its path.
+    pub fn nodes(&self) -> impl Iterator<Item = (&NodeId, &str)> {
+        self.names.iter().map(|(id, name)| (id, name.as_str()))
+    }
+
+    /// Looks a node up by its path, e.g. `uart::pl011::DeviceInner::putc`.
+    pub fn find_node(&self, name: &str) -> Option<&NodeId> {
+        self.names
+            .iter()
+            .find(|(_, other)| *other == name)
+            .map(|(id, _)| id)
+    }
+
+    /// The path of a node, or the id itself if no record knows it.
+    pub fn name<'a>(&'a self, id: &'a str) -> &'a str {
+        self.names.get(id).map_or(id, String::as_str)
+    }
+
+    /// The path of the module defining a node, e.g. `uart::pl011`, if a record knows it.
+    pub fn module(&self, id: &str) -> Option<&str> {
+        self.modules.get(id).map(String::as_str)
+    }
+
+    /// Whether the node is an unsafe item or contains unsafe code.
+    pub fn is_unsafe(&self, id: &str) -> bool {
+        self.unsafe_list.contains(id)
+    }
+
+    /// The functions of the call graph, i.e. the nodes with a body in some record.
+    pub fn functions(&self) -> impl Iterator<Item = &NodeId> {
+        self.call_graph.keys()
+    }
+
+    /// The calls made by `id`, one edge per callee and kind of call.
+    pub fn callees(&self, id: &str) -> &[Edge] {
+        self.call_graph.get(id).map_or(&[], Vec::as_slice)
+    }
+
+    /// The functions calling `id`, with the edge of each call.
+    pub fn callers<'a>(&'a self, id: &'a str) -> impl Iterator<Item = (&'a NodeId, &'a Edge)> {
+        self.reverse_graph
+            .get(id)
+            .into_iter()
+            .flatten()
+            .flat_map(move |caller| {
+                self.callees(caller)
+                    .iter()
+                    .filter(move |edge| edge.callee == id)
+                    .map(move |edge| (caller, edge))
+            })
+    }
+
+    /// Every node reachable from `from`, `from` excluded unless it is recursive.
+    pub fn reachable(&self, from: &str) -> BTreeSet<&NodeId> {
+        let mut visited = BTreeSet::new();
+        let mut queue: Vec<_> = self.callees(from).iter().map(|e| &e.callee).collect();
+        while let Some(id) = queue.pop() {
+            if visited.insert(id) {
+                queue.extend(self.callees(id).iter().map(|e| &e.callee));
+            }
+        }
+        visited
+    }
+
+    /// Every node from which `to` is reachable, `to` excluded unless it is recursive.
+    pub fn reaching(&self, to: &str) -> BTreeSet<&NodeId> {
+        let callers = |id| self.reverse_graph.get(id).into_iter().flatten();
+        let mut visited = BTreeSet::new();
+        let mut queue: Vec<_> = callers(to).collect();
+        while let Some(id) = queue.pop() {
+            if visited.insert(id) {
+                queue.extend(callers(id));
+            }
+        }
+        visited
+    }
+
+    /// A shortest call path from `from` to `to`, both included.
+    pub fn path<'a>(&'a self, from: &'a str, to: &str) -> Option<Vec<&'a str>> {
+        let mut parents: BTreeMap<&str, &str> = BTreeMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(id) = queue.pop_front() {
+            if id == to {
+                let mut path = vec![id];
+                let mut current = id;
+                while let Some(parent) = parents.get(current) {
+                    path.push(parent);
+                    current = parent;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for edge in self.callees(id) {
+                let callee = edge.callee.as_str();
+                if callee != from && !parents.contains_key(callee) {
+                    parents.insert(callee, id);
+                    queue.push_back(callee);
+                }
+            }
+        }
+        None
+    }
+
+    /// Every call path without cycles from `from` to `to` of at most `max_depth` calls.
+    ///
+    /// The number of paths can grow exponentially with the depth.
+    pub fn paths<'a>(&'a self, from: &'a str, to: &str, max_depth: usize) -> Vec<Vec<&'a str>> {
+        let mut paths = Vec::new();
+        self.collect_paths(&mut vec![from], to, max_depth, &mut paths);
+        paths
+    }
+
+    fn collect_paths<'a>(
+        &'a self,
+        stack: &mut Vec<&'a str>,
+        to: &str,
+        max_depth: usize,
+        paths: &mut Vec<Vec<&'a str>>,
+    ) {
+        let current = stack[stack.len() - 1];
+        if current == to {
+            paths.push(stack.clone());
+            return;
+        }
+        if stack.len() > max_depth {
+            return;
+        }
+
+        for edge in self.callees(current) {
+            let callee = edge.callee.as_str();
+            if !stack.contains(&callee) {
+                stack.push(callee);
+                self.collect_paths(stack, to, max_depth, paths);
+                stack.pop();
+            }
+        }
+    }
+
+    /// The `(type, Drop::drop)` pairs of `record` whose drop impl contains unsafe code.
+    pub fn unsafe_drops<'a>(
+        &'a self,
+        record: &'a Record,
+    ) -> impl Iterator<Item = (&'a String, &'a NodeId)> {
+        record
+            .drops
+            .iter()
+            .filter(|(_, drop_fn)| self.is_unsafe(drop_fn))
+    }
+
+    /// The kind of const context `id` is, if any.
+    pub fn const_context(&self, id: &str) -> Option<&OwnerKind> {
+        self.crates()
+            .find_map(|record| record.const_contexts.get(id))
+    }
+
+    /// Whether a library exports the const fn `id`, so that other crates can call it.
+    pub fn is_exported(&self, id: &str) -> bool {
+        self.crates().any(|record| record.exported.contains(id))
+    }
+
+    /// Whether the code of `id` can run at runtime although it is in a const context.
+    ///
+    /// A const fn is runtime-reachable once it is exported, or called, directly or through
+    /// other const fns, outside of a const context or by an exported const fn.
+    /// Consts, statics and anonymous consts are evaluated at compile time.
+    pub fn is_runtime_reachable(&self, id: &str) -> bool {
+        self.is_runtime_reachable_from(id, &mut BTreeSet::new())
+    }
+
+    fn is_runtime_reachable_from(&self, id: &str, visited: &mut BTreeSet<NodeId>) -> bool {
+        if self.const_context(id) != Some(&OwnerKind::ConstFn) {
+            return false;
+        }
+        // Crates which are not analysed, e.g. the users of a library, can call it at runtime.
+        if self.is_exported(id) {
+            return true;
+        }
+
+        self.callers(id)
+            .any(|(caller, _)| match self.const_context(caller) {
+                None => true,
+                Some(OwnerKind::ConstFn) => {
+                    visited.insert(caller.clone())
+                        && self.is_runtime_reachable_from(caller, visited)
+                }
+                Some(_) => false,
+            })
+    }
+
+    /// The crate name, with the version when several versions of the crate are analysed.
+    pub fn display_name(&self, record: &Record) -> String {
+        let multiple_versions = self.crates().any(|other| {
+            other.krate == record.krate && other.package.version != record.package.version
+        });
+
+        if multiple_versions {
+            format!("{}@{}", record.krate, record.package.version)
+        } else {
+            record.krate.clone()
+        }
+    }
+}
+
+// Every compilation has its own node ids, so the items are compared by kind and path.
+// Items are compared by path, as ids differ between the compilations of a package.
+fn production_items(raw_data: &[Record]) -> BTreeMap<PackageId, BTreeSet<(UnsafeKind, String)>> {
+    let mut production: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+    for record in raw_data
+        .iter()
+        .filter(|record| record.target.is_production())
+    {
+        production
+            .entry(record.package.clone())
+            .or_default()
+            .extend(
+                record
+                    .items
+                    .iter()
+                    .map(|item| (item.kind.clone(), item.name.clone())),
+            );
+    }
+    production
+}
+
+fn is_schema_mismatch(e: &io::Error) -> bool {
+    e.get_ref()
+        .is_some_and(|inner| inner.downcast_ref::<SchemaMismatch>().is_some())
+}
+
+impl<'a> IntoIterator for &'a Records {
+    type Item = <&'a Vec<Record> as IntoIterator>::Item;
+    type IntoIter = <&'a Vec<Record> as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.raw_data.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::{Fingerprint, SourceSpan, TargetKind};
+
+    fn record(target: TargetKind, items: &[(&str, &str)]) -> Record {
+        let package = PackageId {
+            name: "toy".to_string(),
+            version: "0.1.0".to_string(),
+            source: "path+/toy".to_string(),
+        };
+        let mut record = Record::new(
+            "toy".to_string(),
+            package,
+            target.clone() as u64,
+            target,
+            CrateKind::Target,
+            "x86_64-unknown-linux-gnu".to_string(),
+            Fingerprint::default(),
+        );
+        let span = SourceSpan {
+            file: "src/lib.rs".to_string(),
+            line: 1,
+            col: 1,
+            end_line: 1,
+            end_col: 1,
+        };
+        for (id, name) in items {
+            record.add_node(id.to_string(), name.to_string());
+            record.add_item(
+                UnsafeKind::Block,
+                id.to_string(),
+                OwnerKind::Fn,
+                span.clone(),
+            );
+        }
+        record
+    }
+
+    fn own_items(records: &Records, target: TargetKind) -> Vec<&str> {
+        let record = records.crates().find(|record| record.target == target);
+        records
+            .own_items(record.unwrap())
+            .map(|item| item.name.as_str())
+            .collect()
+    }
+
+    // The unit tests compile the lib again, under other ids.
+    fn toy() -> Records {
+        let lib = record(TargetKind::Lib, &[("1", "toy::f")]);
+        let test = record(TargetKind::Test, &[("2", "toy::f"), ("3", "toy::tests::t")]);
+        Records::new(vec![lib, test], Vec::new())
+    }
+
+    #[test]
+    fn own_items_of_tests() {
+        let records = toy();
+        assert_eq!(own_items(&records, TargetKind::Lib), ["toy::f"]);
+        assert_eq!(own_items(&records, TargetKind::Test), ["toy::tests::t"]);
+        assert!(records.is_unsafe("2"));
+    }
+
+    #[test]
+    fn own_items_after_filter() {
+        let records = toy().runtime_only().filter(|record| record.krate == "toy");
+        assert_eq!(own_items(&records, TargetKind::Test), ["toy::tests::t"]);
+        // The lib as compiled for the tests is still unsafe.
+        assert!(records.is_unsafe("2"));
+        assert!(records.is_unsafe("3"));
+
+        // Without the lib, the items of the tests are all theirs.
+        let records = records.filter(|record| record.target == TargetKind::Test);
+        assert_eq!(
+            own_items(&records, TargetKind::Test),
+            ["toy::f", "toy::tests::t"]
+        );
+        assert!(records.is_unsafe("2"));
+    }
+}
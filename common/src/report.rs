@@ -0,0 +1,418 @@
+use crate::record::{Edge, NodeId, Record, UnsafeKind};
+use crate::records::Records;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+// Records of the crates named in `filter`, or all of them.
+pub fn selected<'a>(
+    records: &'a Records,
+    filter: &'a Option<Vec<String>>,
+) -> impl Iterator<Item = &'a Record> {
+    records.crates().filter(move |record| match filter {
+        Some(krates) => krates.contains(&record.krate),
+        None => true,
+    })
+}
+
+fn check_unsafe(records: &Records, id: &str) -> String {
+    if records.is_unsafe(id) {
+        format!("{} (unsafe)", records.name(id))
+    } else {
+        records.name(id).to_string()
+    }
+}
+
+pub fn print_summary(records: &Records, filter: &Option<Vec<String>>) {
+    println!("## Summary");
+    println!(
+        "{:<20} {:<12} {:<10} {:<10} {:<10} {:<10}",
+        "Crate", "Target", "Functions", "Blocks", "Impls", "Traits"
+    );
+
+    for record in selected(records, filter) {
+        let count = |kind| {
+            records
+                .own_items(record)
+                .filter(|item| item.kind == kind)
+                .count()
+        };
+        println!(
+            "{:<20} {:<12} {:<10} {:<10} {:<10} {:<10}",
+            records.display_name(record),
+            record.target.to_string(),
+            count(UnsafeKind::Function),
+            count(UnsafeKind::Block),
+            count(UnsafeKind::Impl),
+            count(UnsafeKind::Trait)
+        );
+    }
+
+    print_gaps(records, filter);
+}
+
+// The diagnostics of the analysis, counted by kind, so that gaps in the records are not missed.
+fn print_gaps(records: &Records, filter: &Option<Vec<String>>) {
+    let mut gaps: BTreeMap<_, (usize, BTreeSet<&str>)> = BTreeMap::new();
+    for record in selected(records, filter) {
+        for diagnostic in &record.diagnostics {
+            let (count, krates) = gaps.entry(&diagnostic.kind).or_default();
+            *count += 1;
+            krates.insert(&record.krate);
+        }
+    }
+    if gaps.is_empty() {
+        return;
+    }
+
+    println!("## Analysis Gaps");
+    for (kind, (count, krates)) in gaps {
+        let krates: Vec<_> = krates.into_iter().collect();
+        println!("- {} × {} in {}", count, kind, krates.join(", "));
+    }
+}
+
+pub fn print_unsafe_list(records: &Records, filter: &Option<Vec<String>>) {
+    for record in selected(records, filter) {
+        println!("## Unsafe Item List ({})", record.krate);
+        let items: BTreeSet<_> = records
+            .own_items(record)
+            .map(|item| format!("type: {:?}, id: {}", item.kind, item.name))
+            .collect();
+        for item in items {
+            println!("- {}", item);
+        }
+
+        print_unsafe_drops(records, record);
+        print_unsafe_consts(records, record);
+        print_diagnostics(records, record);
+    }
+}
+
+fn print_diagnostics(records: &Records, record: &Record) {
+    if record.diagnostics.is_empty() {
+        return;
+    }
+
+    println!("## Analysis Gaps ({})", record.krate);
+    for diagnostic in &record.diagnostics {
+        let owner = records.name(&diagnostic.owner);
+        println!("- {} in {} at {}", diagnostic.kind, owner, diagnostic.span);
+    }
+}
+
+fn print_unsafe_drops(records: &Records, record: &Record) {
+    let drops: Vec<_> = records.unsafe_drops(record).collect();
+    if drops.is_empty() {
+        return;
+    }
+
+    println!("## Unsafe Drop Impls ({})", record.krate);
+    for (ty, drop_fn) in drops {
+        println!("- type: {}, drop: {}", ty, records.name(drop_fn));
+    }
+}
+
+fn print_unsafe_consts(records: &Records, record: &Record) {
+    let items: Vec<_> = records
+        .own_items(record)
+        .filter(|item| item.in_const_context)
+        .collect();
+    if items.is_empty() {
+        return;
+    }
+
+    println!("## Unsafe in Const Contexts ({})", record.krate);
+    for item in items {
+        let evaluation = if records.is_runtime_reachable(&item.id) {
+            "runtime-reachable"
+        } else {
+            "compile-time"
+        };
+        println!(
+            "- type: {:?}, owner: {:?}, id: {}, evaluation: {}",
+            item.kind, item.owner, item.name, evaluation
+        );
+    }
+}
+
+// Without a filter, only the crates with findings are listed.
+pub fn print_findings(records: &Records, filter: &Option<Vec<String>>) {
+    for record in selected(records, filter) {
+        if filter.is_none() && record.findings.is_empty() {
+            continue;
+        }
+
+        println!("## Uninitialized Memory ({})", record.krate);
+        for (owner, findings) in &record.findings {
+            for finding in findings {
+                let owner = records.name(owner);
+                println!("- {} in {} at {}", finding.kind, owner, finding.span);
+            }
+        }
+    }
+}
+
+// The functions of `record`, by path
+pub(crate) fn functions_of<'a>(records: &'a Records, record: &'a Record) -> Vec<&'a NodeId> {
+    let mut functions: Vec<_> = record.graph.keys().collect();
+    functions.sort_by_key(|id| records.name(id));
+    functions
+}
+
+// Every record of `krate` is traced, e.g. its lib and its unit tests,
+// or every function if there is none. Callees deeper than `depth` are not printed.
+pub fn print_call_trace(records: &Records, krate: &str, depth: Option<usize>) {
+    let mut traced = false;
+    for record in records.find_crate(krate) {
+        let name = records.display_name(record);
+        println!("## Unsafe Call Trace ({}, {})", name, record.target);
+        print_callees(records, functions_of(records, record), depth);
+        traced = true;
+    }
+
+    if !traced {
+        println!("## Unsafe Call Trace");
+        let mut functions: Vec<_> = records.functions().collect();
+        functions.sort_by_key(|id| records.name(id));
+        print_callees(records, functions, depth);
+    }
+}
+
+fn print_callees(records: &Records, callers: Vec<&NodeId>, depth: Option<usize>) {
+    for caller in callers {
+        println!("- {}", check_unsafe(records, caller));
+        let mut expanded = BTreeSet::from([caller]);
+        let max_depth = depth.unwrap_or(usize::MAX);
+        visit_callee(records, &mut vec![caller], &mut expanded, 1, max_depth);
+    }
+}
+
+// `stack` holds the path from the root, so that recursion is printed only once,
+// and the callees of a function already printed from the same root are not printed again.
+fn visit_callee<'a>(
+    records: &'a Records,
+    stack: &mut Vec<&'a NodeId>,
+    expanded: &mut BTreeSet<&'a NodeId>,
+    depth: usize,
+    max_depth: usize,
+) {
+    if depth > max_depth {
+        return;
+    }
+
+    let caller = stack[stack.len() - 1];
+    for edge in records.callees(caller) {
+        let callee = &edge.callee;
+        if stack.contains(&callee) {
+            println!(
+                "{:indent$}- {} {} (recursive)",
+                "",
+                check_unsafe(records, callee),
+                edge.describe(),
+                indent = depth * 4
+            );
+            continue;
+        }
+
+        let seen = !expanded.insert(callee);
+        println!(
+            "{:indent$}- {} {}{}",
+            "",
+            check_unsafe(records, callee),
+            edge.describe(),
+            if seen { " (see above)" } else { "" },
+            indent = depth * 4
+        );
+        if seen {
+            continue;
+        }
+
+        stack.push(callee);
+        visit_callee(records, stack, expanded, depth + 1, max_depth);
+        stack.pop();
+    }
+}
+
+// Whether the path of `id` is one of `apis`, or ends with one of them, e.g. `mem::transmute`.
+fn is_api(records: &Records, id: &str, apis: &[String]) -> bool {
+    let name = records.name(id);
+    apis.iter()
+        .any(|api| name == api || name.ends_with(&format!("::{}", api)))
+}
+
+// The calls of `record` to any of `apis`, by caller
+pub fn api_calls<'a>(
+    records: &'a Records,
+    record: &'a Record,
+    apis: &'a [String],
+) -> impl Iterator<Item = (&'a NodeId, &'a Edge)> {
+    record.graph.iter().flat_map(move |(caller, edges)| {
+        edges
+            .iter()
+            .filter(move |edge| is_api(records, &edge.callee, apis))
+            .map(move |edge| (caller, edge))
+    })
+}
+
+// Without a filter, only the crates calling the APIs are listed.
+pub fn print_api_calls(records: &Records, filter: &Option<Vec<String>>, apis: &[String]) {
+    for record in selected(records, filter) {
+        let calls: Vec<_> = api_calls(records, record, apis).collect();
+        if filter.is_none() && calls.is_empty() {
+            continue;
+        }
+
+        println!("## Dangerous API Calls ({})", record.krate);
+        for (caller, edge) in calls {
+            println!(
+                "- {} in {} {}",
+                records.name(&edge.callee),
+                records.name(caller),
+                edge.describe()
+            );
+        }
+    }
+}
+
+pub fn print_callers(records: &Records, id: &str, depth: usize) {
+    println!("## Callers ({})", check_unsafe(records, id));
+    visit_caller(records, &mut vec![id], &mut BTreeSet::from([id]), 1, depth);
+}
+
+// Like `visit_callee`, but walks the call graph backwards up to `max_depth` callers.
+fn visit_caller<'a>(
+    records: &'a Records,
+    stack: &mut Vec<&'a str>,
+    expanded: &mut BTreeSet<&'a str>,
+    depth: usize,
+    max_depth: usize,
+) {
+    if depth > max_depth {
+        return;
+    }
+
+    let callee = stack[stack.len() - 1];
+    let mut callers: Vec<_> = records.callers(callee).collect();
+    callers.sort_by_key(|(caller, _)| records.name(caller));
+
+    for (caller, edge) in callers {
+        let recursive = stack.contains(&caller.as_str());
+        let seen = !recursive && !expanded.insert(caller);
+        let note = match (recursive, seen) {
+            (true, _) => " (recursive)",
+            (_, true) => " (see above)",
+            _ => "",
+        };
+        println!(
+            "{:indent$}- {} {}{}",
+            "",
+            check_unsafe(records, caller),
+            edge.describe(),
+            note,
+            indent = (depth - 1) * 4
+        );
+        if recursive || seen {
+            continue;
+        }
+
+        stack.push(caller);
+        visit_caller(records, stack, expanded, depth + 1, max_depth);
+        stack.pop();
+    }
+}
+
+// The unsafe items of each crate, by crate name
+pub(crate) fn items_by_crate<'a>(
+    records: &'a Records,
+    filter: &'a Option<Vec<String>>,
+) -> BTreeMap<&'a str, BTreeSet<(UnsafeKind, &'a str)>> {
+    let mut items: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+    for record in selected(records, filter) {
+        items.entry(record.krate.as_str()).or_default().extend(
+            records
+                .own_items(record)
+                .map(|item| (item.kind.clone(), item.name.as_str())),
+        );
+    }
+    items
+}
+
+// Lists the unsafe items added (`+`) and removed (`-`) since `baseline`.
+pub fn print_diff(baseline: &Records, records: &Records, filter: &Option<Vec<String>>) {
+    let old = items_by_crate(baseline, filter);
+    let new = items_by_crate(records, filter);
+    let krates: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+
+    let empty = BTreeSet::new();
+    let mut changed = false;
+    for krate in krates {
+        let old = old.get(krate).unwrap_or(&empty);
+        let new = new.get(krate).unwrap_or(&empty);
+        if old == new {
+            continue;
+        }
+
+        changed = true;
+        println!("## Unsafe Item Diff ({})", krate);
+        for (kind, name) in new.difference(old) {
+            println!("+ type: {:?}, id: {}", kind, name);
+        }
+        for (kind, name) in old.difference(new) {
+            println!("- type: {:?}, id: {}", kind, name);
+        }
+    }
+
+    if !changed {
+        println!("No changes in unsafe items.");
+    }
+}
+
+pub fn report(
+    out_dir: &Path,
+    filter: Option<Vec<String>>,
+    production_only: bool,
+    runtime_only: bool,
+    verbose: bool,
+    call_trace: bool,
+    uninit: bool,
+) {
+    let mut records = match Records::load_from(out_dir) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("Failed to read records: {}", e);
+            return;
+        }
+    };
+    for (path, reason) in records.skipped() {
+        eprintln!("Skipped {}: {}", path, reason);
+    }
+    if production_only {
+        records = records.production_only();
+    }
+    if runtime_only {
+        records = records.runtime_only();
+    }
+
+    print_summary(&records, &filter);
+
+    if verbose {
+        println!();
+        print_unsafe_list(&records, &filter);
+    }
+
+    if uninit {
+        println!();
+        print_findings(&records, &filter);
+    }
+
+    if call_trace && filter.is_some() {
+        println!();
+        let krates: BTreeSet<_> = selected(&records, &filter)
+            .map(|record| record.krate.as_str())
+            .collect();
+        for krate in krates {
+            print_call_trace(&records, krate, None);
+        }
+    }
+}
@@ -0,0 +1,585 @@
+//! The records as a SARIF 2.1.0 log, for code scanning and code review tools.
+//!
+//! Every unsafe item kind, finding kind and analysis gap is a rule, as are unsafe blocks
+//! without a `// SAFETY:` comment. The violations of the `check` policy of the project,
+//! calls to its dangerous APIs and exceeded limits, are errors. Results carry a fingerprint
+//! derived from the identity of the item rather than its position, so that a result
+//! found in a baseline is recognised after the code around it moved.
+
+use crate::check::Policy;
+use crate::record::{fnv1a, DiagnosticKind, FindingKind, Record, SourceSpan, UnsafeKind};
+use crate::records::Records;
+use crate::report::api_calls;
+
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+// The key of the fingerprints in `partialFingerprints`, bumped whenever they are computed differently
+const FINGERPRINT: &str = "utrace/v2";
+
+struct Rule {
+    id: &'static str,
+    name: &'static str,
+    description: &'static str,
+    level: &'static str,
+}
+
+const RULES: [Rule; 15] = [
+    Rule {
+        id: "unsafe-function",
+        name: "UnsafeFunction",
+        description: "Unsafe function",
+        level: "note",
+    },
+    Rule {
+        id: "unsafe-block",
+        name: "UnsafeBlock",
+        description: "Unsafe block",
+        level: "note",
+    },
+    Rule {
+        id: "unjustified-unsafe-block",
+        name: "UnjustifiedUnsafeBlock",
+        description: "Unsafe block without a `// SAFETY:` comment right above it",
+        level: "warning",
+    },
+    Rule {
+        id: "unsafe-trait",
+        name: "UnsafeTrait",
+        description: "Unsafe trait",
+        level: "note",
+    },
+    Rule {
+        id: "unsafe-impl",
+        name: "UnsafeImpl",
+        description: "Unsafe impl",
+        level: "note",
+    },
+    Rule {
+        id: "assume-init-uninit",
+        name: "AssumeInitUninit",
+        description: "MaybeUninit::uninit().assume_init() reads uninitialized memory",
+        level: "warning",
+    },
+    Rule {
+        id: "mem-uninitialized",
+        name: "MemUninitialized",
+        description: "mem::uninitialized() creates uninitialized memory",
+        level: "warning",
+    },
+    Rule {
+        id: "mem-zeroed",
+        name: "MemZeroed",
+        description: "mem::zeroed() of a type without a valid all-zero pattern",
+        level: "warning",
+    },
+    Rule {
+        id: "vec-set-len",
+        name: "VecSetLen",
+        description: "Vec::set_len() after Vec::with_capacity() exposes uninitialized elements",
+        level: "warning",
+    },
+    Rule {
+        id: "unresolved-call",
+        name: "UnresolvedCall",
+        description: "Call the analysis could not resolve, the call graph is incomplete",
+        level: "note",
+    },
+    Rule {
+        id: "deny-api",
+        name: "DangerousApi",
+        description: "Call to a dangerous API of the project configuration",
+        level: "error",
+    },
+    Rule {
+        id: "max-functions",
+        name: "MaxFunctions",
+        description: "More unsafe functions than the project allows",
+        level: "error",
+    },
+    Rule {
+        id: "max-blocks",
+        name: "MaxBlocks",
+        description: "More unsafe blocks than the project allows",
+        level: "error",
+    },
+    Rule {
+        id: "max-impls",
+        name: "MaxImpls",
+        description: "More unsafe impls than the project allows",
+        level: "error",
+    },
+    Rule {
+        id: "max-traits",
+        name: "MaxTraits",
+        description: "More unsafe traits than the project allows",
+        level: "error",
+    },
+];
+
+struct SarifResult<'a> {
+    rule: &'static str,
+    level: Option<&'static str>,
+    message: String,
+    span: &'a SourceSpan,
+    // the path of the item the result is about, if any
+    name: Option<&'a str>,
+    fingerprint: String,
+}
+
+fn unsafe_rule(kind: &UnsafeKind) -> &'static str {
+    match kind {
+        UnsafeKind::Function => "unsafe-function",
+        UnsafeKind::Block => "unsafe-block",
+        UnsafeKind::Trait => "unsafe-trait",
+        UnsafeKind::Impl => "unsafe-impl",
+    }
+}
+
+fn finding_rule(kind: &FindingKind) -> &'static str {
+    match kind {
+        FindingKind::AssumeInitUninit => "assume-init-uninit",
+        FindingKind::MemUninitialized => "mem-uninitialized",
+        FindingKind::MemZeroed { .. } => "mem-zeroed",
+        FindingKind::VecSetLen => "vec-set-len",
+    }
+}
+
+fn diagnostic_rule(kind: &DiagnosticKind) -> &'static str {
+    match kind {
+        DiagnosticKind::UnresolvedCall => "unresolved-call",
+    }
+}
+
+fn results<'a>(
+    records: &'a Records,
+    record: &'a Record,
+    policy: &'a Policy,
+) -> Vec<SarifResult<'a>> {
+    let mut results = Vec::new();
+    // `identity` leaves out the position, so that moved code keeps its fingerprint.
+    let mut push = |rule, level, message, span, name, identity: String| {
+        results.push(SarifResult {
+            rule,
+            level,
+            message,
+            span,
+            name: Some(name),
+            fingerprint: format!("{}|{}|{}", rule, record.krate, identity),
+        })
+    };
+
+    // Blocks are reported one by one below.
+    for item in records.own_items(record) {
+        if item.kind == UnsafeKind::Block {
+            continue;
+        }
+        let rule = unsafe_rule(&item.kind);
+        let message = format!("{:?}: {}", item.kind, item.name);
+        push(
+            rule,
+            None,
+            message,
+            &item.span,
+            &item.name,
+            item.name.clone(),
+        );
+    }
+
+    // Only the blocks of the owners left in `items`, e.g. not the ones of the lib in its unit tests
+    let owners: BTreeSet<_> = records
+        .own_items(record)
+        .filter(|item| item.kind == UnsafeKind::Block)
+        .map(|item| &item.id)
+        .collect();
+    for block in record
+        .blocks
+        .iter()
+        .filter(|block| owners.contains(&block.owner))
+    {
+        // by its source, so that adding a block does not change the others
+        let owner = records.name(&block.owner);
+        let identity = format!("{}|{}", owner, block.digest);
+        let message = format!("Unsafe block in {}", owner);
+        push(
+            "unsafe-block",
+            None,
+            message,
+            &block.span,
+            owner,
+            identity.clone(),
+        );
+        if !block.justified {
+            let message = format!("Unsafe block without a SAFETY comment in {}", owner);
+            push(
+                "unjustified-unsafe-block",
+                None,
+                message,
+                &block.span,
+                owner,
+                identity,
+            );
+        }
+    }
+
+    let level = policy.deny_uninit.then_some("error");
+    for (owner, findings) in &record.findings {
+        let owner = records.name(owner);
+        for finding in findings {
+            let message = format!("{} in {}", finding.kind, owner);
+            let identity = format!("{}|{}", owner, finding.kind);
+            let rule = finding_rule(&finding.kind);
+            push(rule, level, message, &finding.span, owner, identity);
+        }
+    }
+
+    for diagnostic in &record.diagnostics {
+        let owner = records.name(&diagnostic.owner);
+        let message = format!("{} in {}", diagnostic.kind, owner);
+        let rule = diagnostic_rule(&diagnostic.kind);
+        push(
+            rule,
+            None,
+            message,
+            &diagnostic.span,
+            owner,
+            owner.to_string(),
+        );
+    }
+
+    for (caller, edge) in api_calls(records, record, &policy.deny_apis) {
+        let caller = records.name(caller);
+        let callee = records.name(&edge.callee);
+        let message = format!("Dangerous API {} called in {}", callee, caller);
+        let identity = format!("{}|{}", caller, callee);
+        for site in &edge.sites {
+            push(
+                "deny-api",
+                None,
+                message.clone(),
+                &site.span,
+                caller,
+                identity.clone(),
+            );
+        }
+    }
+
+    hash_fingerprints(&mut results);
+    results
+}
+
+// The limits are counted over all selected crates, so their results are about the manifest.
+// A baseline exceeding a limit hides it only with the same count, as more items are a new result.
+fn limit_results<'a>(
+    records: &Records,
+    selected: &[&Record],
+    policy: &Policy,
+    manifest: &'a SourceSpan,
+) -> Vec<SarifResult<'a>> {
+    let mut results: Vec<_> = policy
+        .exceeded(records, selected)
+        .into_iter()
+        .map(|exceeded| SarifResult {
+            rule: exceeded.rule,
+            level: None,
+            message: exceeded.message(),
+            span: manifest,
+            name: None,
+            fingerprint: format!("{}|{}", exceeded.rule, exceeded.count),
+        })
+        .collect();
+    hash_fingerprints(&mut results);
+    results
+}
+
+// The same item can have several results of a rule, e.g. two calls to an API.
+fn hash_fingerprints(results: &mut [SarifResult]) {
+    let mut seen: BTreeMap<String, usize> = BTreeMap::new();
+    for result in results {
+        let count = seen.entry(result.fingerprint.clone()).or_default();
+        result.fingerprint = fnv1a(&format!("{}|{}", result.fingerprint, count));
+        *count += 1;
+    }
+}
+
+// Relative paths are relative to the workspace root, i.e. the checkout.
+fn artifact_location(file: &str) -> Value {
+    let encoded: String = file
+        .chars()
+        .map(|c| match c {
+            ' ' | '%' | '#' | '?' => format!("%{:02X}", c as u32),
+            c => c.to_string(),
+        })
+        .collect();
+
+    if file.starts_with('/') {
+        json!({ "uri": format!("file://{}", encoded) })
+    } else {
+        json!({ "uri": encoded, "uriBaseId": "%SRCROOT%" })
+    }
+}
+
+fn location(span: &SourceSpan, name: Option<&str>) -> Value {
+    let mut location = json!({
+        "physicalLocation": {
+            "artifactLocation": artifact_location(&span.file),
+            "region": {
+                "startLine": span.line,
+                "startColumn": span.col,
+                "endLine": span.end_line,
+                "endColumn": span.end_col,
+            },
+        },
+    });
+    if let Some(name) = name {
+        location["logicalLocations"] = json!([{ "fullyQualifiedName": name }]);
+    }
+    location
+}
+
+/// The results of the `selected` records as a SARIF log, with the violations of `policy`.
+///
+/// With a `baseline`, only the results which are not in the baseline are reported.
+pub fn log(
+    records: &Records,
+    selected: &[&Record],
+    policy: &Policy,
+    baseline: Option<&Records>,
+) -> Value {
+    // relative to the workspace root, like the sources
+    let manifest = SourceSpan {
+        file: "Cargo.toml".to_string(),
+        line: 1,
+        col: 1,
+        end_line: 1,
+        end_col: 1,
+    };
+
+    let mut known = BTreeSet::new();
+    if let Some(baseline) = baseline {
+        let crates: Vec<_> = baseline.crates().collect();
+        for record in &crates {
+            known.extend(
+                results(baseline, record, policy)
+                    .into_iter()
+                    .map(|result| result.fingerprint),
+            );
+        }
+        known.extend(
+            limit_results(baseline, &crates, policy, &manifest)
+                .into_iter()
+                .map(|result| result.fingerprint),
+        );
+    }
+
+    let rules: Vec<_> = RULES
+        .iter()
+        .map(|rule| {
+            json!({
+                "id": rule.id,
+                "name": rule.name,
+                "shortDescription": { "text": rule.description },
+                "defaultConfiguration": { "level": rule.level },
+            })
+        })
+        .collect();
+
+    let results: Vec<_> = selected
+        .iter()
+        .flat_map(|record| results(records, record, policy))
+        .chain(limit_results(records, selected, policy, &manifest))
+        .filter(|result| !known.contains(&result.fingerprint))
+        .map(|result| {
+            let index = RULES.iter().position(|rule| rule.id == result.rule);
+            let mut value = json!({
+                "ruleId": result.rule,
+                "ruleIndex": index,
+                "message": { "text": result.message },
+                "locations": [location(result.span, result.name)],
+                "partialFingerprints": { FINGERPRINT: result.fingerprint },
+            });
+            if let Some(level) = result.level {
+                value["level"] = json!(level);
+            }
+            if baseline.is_some() {
+                value["baselineState"] = json!("new");
+            }
+            value
+        })
+        .collect();
+
+    json!({
+        "$schema": SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "utrace",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": results,
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::{CrateKind, Fingerprint, OwnerKind, PackageId, TargetKind};
+
+    fn span(line: usize) -> SourceSpan {
+        SourceSpan {
+            file: "src/lib.rs".to_string(),
+            line,
+            col: 5,
+            end_line: line,
+            end_col: 20,
+        }
+    }
+
+    fn record() -> Record {
+        let package = PackageId {
+            name: "toy".to_string(),
+            version: "0.1.0".to_string(),
+            source: "path+/toy".to_string(),
+        };
+        Record::new(
+            "toy".to_string(),
+            package,
+            1,
+            TargetKind::Lib,
+            CrateKind::Target,
+            "x86_64-unknown-linux-gnu".to_string(),
+            Fingerprint::default(),
+        )
+    }
+
+    // `toy::f` with the (source, justified) blocks, one every other line from `line`
+    fn records(line: usize, blocks: &[(&str, bool)]) -> Records {
+        let mut record = record();
+        let id = "0123".to_string();
+        record.add_node(id.clone(), "toy::f".to_string());
+        record.add_fn(id.clone());
+        record.add_item(UnsafeKind::Block, id.clone(), OwnerKind::Fn, span(line));
+        for (i, (source, justified)) in blocks.iter().enumerate() {
+            let span = span(line + 2 * i);
+            record.add_block(id.clone(), span, *justified, fnv1a(source));
+        }
+        Records::new(vec![record], Vec::new())
+    }
+
+    // `count` functions with an unsafe block each
+    fn functions(count: usize) -> Records {
+        let mut record = record();
+        for i in 0..count {
+            let id = format!("{:04}", i);
+            record.add_node(id.clone(), format!("toy::f{}", i));
+            record.add_item(UnsafeKind::Block, id.clone(), OwnerKind::Fn, span(i));
+            record.add_block(id, span(i), true, fnv1a("unsafe{}"));
+        }
+        Records::new(vec![record], Vec::new())
+    }
+
+    fn fingerprints(records: &Records) -> Vec<(&'static str, String)> {
+        let record = records.crates().next().unwrap();
+        results(records, record, &Policy::default())
+            .into_iter()
+            .map(|result| (result.rule, result.fingerprint))
+            .collect()
+    }
+
+    // (rule, line) of the results which are not in the baseline
+    fn new_results(records: &Records, baseline: &Records, policy: &Policy) -> Vec<(String, u64)> {
+        let selected: Vec<_> = records.crates().collect();
+        let log = log(records, &selected, policy, Some(baseline));
+        log["runs"][0]["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| {
+                let region = &result["locations"][0]["physicalLocation"]["region"];
+                let rule = result["ruleId"].as_str().unwrap().to_string();
+                (rule, region["startLine"].as_u64().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fnv1a_vectors() {
+        assert_eq!(fnv1a(""), "cbf29ce484222325");
+        assert_eq!(fnv1a("a"), "af63dc4c8601ec8c");
+        assert_eq!(fnv1a("foobar"), "85944171f73967e8");
+    }
+
+    #[test]
+    fn result_per_block() {
+        let records = records(10, &[("unsafe{a()}", true), ("unsafe{b()}", false)]);
+        let rules: Vec<_> = fingerprints(&records)
+            .into_iter()
+            .map(|(rule, _)| rule)
+            .collect();
+        assert_eq!(
+            rules,
+            ["unsafe-block", "unsafe-block", "unjustified-unsafe-block"]
+        );
+    }
+
+    #[test]
+    fn fingerprints_of_moved_code() {
+        let blocks = [("unsafe{a()}", true), ("unsafe{b()}", false)];
+        let moved = fingerprints(&records(42, &blocks));
+        assert_eq!(fingerprints(&records(10, &blocks)), moved);
+
+        // Blocks of the same function are told apart.
+        assert_ne!(moved[0].1, moved[1].1);
+    }
+
+    #[test]
+    fn baseline_of_moved_code() {
+        let blocks = [("unsafe{a()}", true), ("unsafe{b()}", false)];
+        let before = records(10, &blocks);
+        let after = records(42, &blocks);
+        assert!(new_results(&after, &before, &Policy::default()).is_empty());
+    }
+
+    #[test]
+    fn baseline_of_added_block() {
+        let before = records(10, &[("unsafe{a()}", true), ("unsafe{b()}", true)]);
+        let blocks = [
+            ("unsafe{c()}", false),
+            ("unsafe{a()}", true),
+            ("unsafe{b()}", true),
+        ];
+        let after = records(10, &blocks);
+        assert_eq!(
+            new_results(&after, &before, &Policy::default()),
+            [
+                ("unsafe-block".to_string(), 10),
+                ("unjustified-unsafe-block".to_string(), 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn baseline_of_exceeded_limit() {
+        let policy = Policy {
+            max_blocks: Some(1),
+            ..Policy::default()
+        };
+        // Only the blocks of the new functions are new.
+        let new = new_results(&functions(3), &functions(2), &policy);
+        let limits: Vec<_> = new
+            .iter()
+            .filter(|(rule, _)| rule == "max-blocks")
+            .collect();
+        assert_eq!(limits, [&("max-blocks".to_string(), 1)]);
+
+        let new = new_results(&functions(2), &functions(2), &policy);
+        assert!(new.is_empty());
+    }
+}
@@ -0,0 +1,15 @@
+use clap::Parser;
+use utrace_driver::cli::Cli;
+
+// `cargo utrace ...` runs `cargo-utrace utrace ...`.
+// Like other cargo subcommands, it works on the project of the current directory.
+#[derive(Parser)]
+#[command(name = "cargo", bin_name = "cargo")]
+enum CargoCli {
+    Utrace(Cli),
+}
+
+fn main() {
+    let CargoCli::Utrace(args) = CargoCli::parse();
+    utrace_driver::run(args);
+}
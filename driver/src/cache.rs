@@ -0,0 +1,360 @@
+use crate::exit::Exit;
+
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use utrace_common::{config, Record, SCHEMA_VERSION};
+
+// A compilation of the last build
+pub struct Unit {
+    pub package_id: String,
+    // e.g. `serde` or `build-script-build`
+    pub name: String,
+    // e.g. `lib`, `bin` or `custom-build`
+    pub kind: String,
+    // `-C extra-filename`, missing for binaries and examples, which are copied out of `deps`
+    pub hash: Option<String>,
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}) of {}", self.name, self.kind, self.package_id)
+    }
+}
+
+// The compilations of the last build, as reported by `cargo build --message-format=json`.
+#[derive(Default)]
+pub struct Units {
+    units: Vec<Unit>,
+}
+
+impl Units {
+    pub fn parse(stdout: &[u8]) -> Self {
+        let mut units = Self::default();
+        let messages = stdout
+            .split(|b| *b == b'\n')
+            .filter_map(|line| serde_json::from_slice::<Value>(line).ok())
+            .filter(|message| message["reason"] == "compiler-artifact");
+
+        for message in messages {
+            let hash = message["filenames"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .find_map(|path| unit_hash(Path::new(path)));
+            let field = |value: &Value| value.as_str().unwrap_or_default().to_string();
+
+            units.units.push(Unit {
+                package_id: field(&message["package_id"]),
+                name: field(&message["target"]["name"]),
+                kind: field(&message["target"]["kind"][0]),
+                hash,
+            });
+        }
+
+        units
+    }
+
+    // (crate, target kind) of the binaries and examples, which have no hash
+    fn key(unit: &Unit) -> (String, String) {
+        (unit.name.replace('-', "_"), unit.kind.clone())
+    }
+
+    fn hashes(&self) -> BTreeSet<&str> {
+        self.units
+            .iter()
+            .filter_map(|unit| unit.hash.as_deref())
+            .collect()
+    }
+
+    fn uplifted(&self) -> BTreeSet<(String, String)> {
+        self.units
+            .iter()
+            .filter(|unit| unit.hash.is_none())
+            .map(Self::key)
+            .collect()
+    }
+}
+
+// e.g. `deps/libserde-89551aa7d792549c.rlib` or `build/serde-b2abab732441ffc7/build-script-build`
+fn unit_hash(path: &Path) -> Option<String> {
+    path.iter().rev().take(2).find_map(|name| {
+        let stem = name.to_str()?.split('.').next()?;
+        let (_, hash) = stem.rsplit_once('-')?;
+        let is_hash = hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit());
+        is_hash.then(|| hash.to_string())
+    })
+}
+
+// Cargo builds into a target dir of its own, so that every compilation it skips
+// has a record from an earlier run. Anything cargo does not track and which changes
+// the records invalidates the whole directory, e.g. the `settings` of the tracer.
+// Only the files utrace owns are removed, and only from a directory with its stamp.
+pub fn prepare(out_dir: &Path, clean: bool, settings: &str) -> PathBuf {
+    let build_dir = out_dir.join("build");
+    let stamp_path = build_dir.join("utrace.stamp");
+
+    let is_empty = match fs::read_dir(out_dir) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(e) => e.kind() == io::ErrorKind::NotFound,
+    };
+    if !is_empty && !stamp_path.is_file() {
+        eprintln!(
+            "{} is not an output directory of utrace and not empty, choose another --out-dir.",
+            out_dir.display()
+        );
+        Exit::InvalidUsage.exit();
+    }
+
+    let plugin_modified = fs::metadata(config::plugin_bin())
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    let stamp = format!(
+        "schema: {}\nutrace: {}\nplugin: {}\n{}",
+        SCHEMA_VERSION,
+        env!("CARGO_PKG_VERSION"),
+        plugin_modified,
+        settings
+    );
+
+    let fresh = fs::read_to_string(&stamp_path).is_ok_and(|old| old == stamp);
+    if (clean || !fresh) && !is_empty {
+        remove_owned(out_dir);
+    }
+
+    fs::create_dir_all(&build_dir).unwrap();
+    fs::write(stamp_path, stamp).unwrap();
+    build_dir
+}
+
+// The build directory, the records and the records being written
+fn remove_owned(out_dir: &Path) {
+    for entry in fs::read_dir(out_dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            if name == "build" {
+                fs::remove_dir_all(&path).unwrap();
+            }
+        } else if name.ends_with(".record") || name.contains(".record.tmp-") {
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
+
+// Removes the records of compilations which are not part of the build anymore,
+// e.g. after a change of features, and records which cannot be read.
+pub fn prune(out_dir: &Path, units: &Units) {
+    let hashes = units.hashes();
+    let uplifted_units = units.uplifted();
+    let mut uplifted = BTreeMap::new();
+
+    for entry in fs::read_dir(out_dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            continue;
+        }
+
+        let name = path.display().to_string();
+        if name.contains(".record.tmp-") {
+            fs::remove_file(&path).unwrap();
+            continue;
+        }
+        if path.extension().map_or(true, |ext| ext != "record") {
+            continue;
+        }
+
+        let record = match Record::load(&name) {
+            Ok(record) => record,
+            Err(_) => {
+                fs::remove_file(&path).unwrap();
+                continue;
+            }
+        };
+
+        if hashes.contains(record.fingerprint.unit.as_str()) {
+            continue;
+        }
+
+        // Only the latest record of a binary is kept.
+        let key = (record.krate.clone(), record.target.to_string());
+        if uplifted_units.contains(&key) {
+            let timestamp = record.header.timestamp;
+            match uplifted.get(&key) {
+                Some((latest, _)) if *latest > timestamp => {
+                    fs::remove_file(&path).unwrap();
+                }
+                _ => {
+                    if let Some((_, older)) = uplifted.insert(key, (timestamp, path)) {
+                        fs::remove_file(older).unwrap();
+                    }
+                }
+            }
+            continue;
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+// The compilations of `units` which have no record, e.g. because the analysis crashed.
+pub fn unanalysed<'a>(out_dir: &Path, units: &'a Units) -> Vec<&'a Unit> {
+    let mut hashes = BTreeSet::new();
+    let mut uplifted = BTreeSet::new();
+    for record in records(out_dir) {
+        uplifted.insert((record.krate.clone(), record.target.to_string()));
+        hashes.insert(record.fingerprint.unit);
+    }
+
+    units
+        .units
+        .iter()
+        .filter(|unit| match &unit.hash {
+            Some(hash) => !hashes.contains(hash),
+            None => !uplifted.contains(&Units::key(unit)),
+        })
+        .collect()
+}
+
+fn records(out_dir: &Path) -> impl Iterator<Item = Record> {
+    fs::read_dir(out_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "record"))
+        .filter_map(|path| Record::load(&path.display().to_string()).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use utrace_common::{CrateKind, Fingerprint, PackageId, TargetKind};
+
+    // `cargo build --message-format=json` of a package with a build script, a lib and a bin
+    const BUILD: &str = r#"{"reason":"compiler-artifact","package_id":"path+file:///tmp/cap#0.1.0","manifest_path":"/tmp/cap/Cargo.toml","target":{"kind":["custom-build"],"crate_types":["bin"],"name":"build-script-build","src_path":"/tmp/cap/build.rs","edition":"2021","doc":false,"doctest":false,"test":false},"profile":{"opt_level":"0","debuginfo":0,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/tmp/cap/target/debug/build/cap-232af77b8c8c7a7f/build-script-build"],"executable":null,"fresh":false}
+{"reason":"build-script-executed","package_id":"path+file:///tmp/cap#0.1.0","linked_libs":[],"linked_paths":[],"cfgs":[],"env":[],"out_dir":"/tmp/cap/target/debug/build/cap-f4d019eccc6da887/out"}
+{"reason":"compiler-artifact","package_id":"path+file:///tmp/cap#0.1.0","manifest_path":"/tmp/cap/Cargo.toml","target":{"kind":["lib"],"crate_types":["lib"],"name":"cap","src_path":"/tmp/cap/src/lib.rs","edition":"2021","doc":true,"doctest":true,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/tmp/cap/target/debug/libcap.rlib","/tmp/cap/target/debug/deps/libcap-65741c888eef6c71.rmeta"],"executable":null,"fresh":false}
+{"reason":"compiler-artifact","package_id":"path+file:///tmp/cap#0.1.0","manifest_path":"/tmp/cap/Cargo.toml","target":{"kind":["bin"],"crate_types":["bin"],"name":"cap","src_path":"/tmp/cap/src/main.rs","edition":"2021","doc":true,"doctest":false,"test":true},"profile":{"opt_level":"0","debuginfo":2,"debug_assertions":true,"overflow_checks":true,"test":false},"features":[],"filenames":["/tmp/cap/target/debug/cap"],"executable":"/tmp/cap/target/debug/cap","fresh":false}
+{"reason":"build-finished","success":true}
+"#;
+
+    // An empty output directory of its own for each test
+    fn out_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("utrace-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Saves a record of `cap` made from the compilation `unit`, written at `timestamp`
+    fn save(dir: &Path, target: TargetKind, unit: &str, id: u64, timestamp: u64) -> PathBuf {
+        let package = PackageId {
+            name: "cap".to_string(),
+            version: "0.1.0".to_string(),
+            source: "path+/tmp/cap".to_string(),
+        };
+        let fingerprint = Fingerprint {
+            unit: unit.to_string(),
+            ..Default::default()
+        };
+        let mut record = Record::new(
+            "cap".to_string(),
+            package,
+            id,
+            target,
+            CrateKind::Target,
+            "x86_64-unknown-linux-gnu".to_string(),
+            fingerprint,
+        );
+        record.header.timestamp = timestamp;
+        let dir = dir.display().to_string();
+        record.save(&dir).unwrap();
+        PathBuf::from(record.path(&dir))
+    }
+
+    fn names(units: &[&Unit]) -> Vec<String> {
+        units.iter().map(|unit| unit.to_string()).collect()
+    }
+
+    #[test]
+    fn parse() {
+        let units = Units::parse(BUILD.as_bytes());
+        let units: Vec<_> = units
+            .units
+            .iter()
+            .map(|unit| (unit.name.as_str(), unit.kind.as_str(), unit.hash.as_deref()))
+            .collect();
+        assert_eq!(
+            units,
+            [
+                (
+                    "build-script-build",
+                    "custom-build",
+                    Some("232af77b8c8c7a7f")
+                ),
+                ("cap", "lib", Some("65741c888eef6c71")),
+                // copied out of `deps` without the hash
+                ("cap", "bin", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn prune_stale_records() {
+        let dir = out_dir("prune");
+        let units = Units::parse(BUILD.as_bytes());
+
+        let lib = save(&dir, TargetKind::Lib, "65741c888eef6c71", 1, 10);
+        // e.g. the lib before a change of features
+        let stale = save(&dir, TargetKind::Lib, "0123456789abcdef", 2, 5);
+        // the records of the bin are told apart by their crate and target, the latest is kept
+        let old_bin = save(&dir, TargetKind::Bin, "1111111111111111", 3, 5);
+        let bin = save(&dir, TargetKind::Bin, "2222222222222222", 4, 10);
+        let tmp = dir.join("cap-0.1.0-0000000000000005.record.tmp-1");
+        fs::write(&tmp, "{").unwrap();
+        let broken = dir.join("cap-0.1.0-0000000000000006.record");
+        fs::write(&broken, "{").unwrap();
+
+        prune(&dir, &units);
+        assert!(lib.is_file());
+        assert!(!stale.exists());
+        assert!(!old_bin.exists());
+        assert!(bin.is_file());
+        assert!(!tmp.exists());
+        assert!(!broken.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unanalysed_units() {
+        let dir = out_dir("unanalysed");
+        let units = Units::parse(BUILD.as_bytes());
+
+        save(&dir, TargetKind::Lib, "65741c888eef6c71", 1, 10);
+        assert_eq!(
+            names(&unanalysed(&dir, &units)),
+            [
+                "build-script-build (custom-build) of path+file:///tmp/cap#0.1.0",
+                "cap (bin) of path+file:///tmp/cap#0.1.0",
+            ]
+        );
+
+        // The bin has no hash, its record is found by its crate and target.
+        save(&dir, TargetKind::Bin, "2222222222222222", 2, 10);
+        save(&dir, TargetKind::BuildScript, "232af77b8c8c7a7f", 3, 10);
+        assert!(unanalysed(&dir, &units).is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,21 +1,323 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+use serde::Deserialize;
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 pub struct Cli {
-    #[arg(short, long)]
-    pub init: bool,
+    #[command(subcommand)]
+    pub command: Command,
+}
 
-    #[arg(short, long)]
-    pub utrace: Option<PathBuf>,
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Build the plugin
+    Init,
+    /// Check the installation of utrace and the output directory of the project
+    Doctor(ProjectArgs),
+    /// Build the project with the plugin and collect its records
+    Analyze(AnalyzeArgs),
+    /// Count the unsafe items of each crate
+    Summary(SummaryArgs),
+    /// List the unsafe items of each crate
+    List(ListArgs),
+    /// Print the call trace of the functions of each crate
+    Trace(TraceArgs),
+    /// Print the callers of a function
+    Callers(CallersArgs),
+    /// Compare the unsafe items with the records of an earlier analysis
+    Diff(DiffArgs),
+    /// Fail if the unsafe code exceeds the given limits
+    Check(CheckArgs),
+    /// Write the records to a file
+    Export(ExportArgs),
+}
 
-    #[arg(short, long)]
-    pub verbose: bool,
+// Where the records of a project are
+#[derive(Args, Debug, Default, Clone)]
+pub struct ProjectArgs {
+    // Defaults to `UTRACE_OUT_DIR`, then to `target/utrace` of the project.
+    #[arg(short, long, env = "UTRACE_OUT_DIR")]
+    pub out_dir: Option<PathBuf>,
+
+    #[arg(long, value_name = "PATH")]
+    pub manifest_path: Option<PathBuf>,
+}
+
+// Which records are reported
+#[derive(Args, Debug, Clone)]
+pub struct RecordsArgs {
+    #[command(flatten)]
+    pub project: ProjectArgs,
 
     #[arg(short, long, value_delimiter = ',')]
     pub filter: Option<Vec<String>>,
 
+    #[arg(short, long, value_delimiter = ',')]
+    pub exclude: Vec<String>,
+
+    #[arg(long)]
+    pub production_only: bool,
+
+    #[arg(long)]
+    pub runtime_only: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AnalyzeArgs {
+    // Defaults to `UTRACE_OUT_DIR`, then to `target/utrace` of the project.
+    #[arg(short, long, env = "UTRACE_OUT_DIR")]
+    pub out_dir: Option<PathBuf>,
+
+    #[arg(long)]
+    pub all_targets: bool,
+
+    // Analyses the workspace members only, dependencies are compiled by rustc.
+    #[arg(long)]
+    pub workspace_only: bool,
+
+    // Analyses every crate again instead of only the recompiled ones.
+    #[arg(long)]
+    pub clean: bool,
+
+    #[command(flatten)]
+    pub cargo: CargoArgs,
+
+    // Defaults to the configuration of the project, then to text.
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct SummaryArgs {
+    #[command(flatten)]
+    pub records: RecordsArgs,
+
+    // Defaults to the configuration of the project, then to text.
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    #[command(flatten)]
+    pub records: RecordsArgs,
+
+    // Also lists the uninitialized memory findings.
+    #[arg(long)]
+    pub uninit: bool,
+
+    // Defaults to the configuration of the project, then to text.
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct TraceArgs {
+    #[command(flatten)]
+    pub records: RecordsArgs,
+
+    // The callees deeper than this are not printed.
+    #[arg(short, long)]
+    pub depth: Option<usize>,
+
+    // Defaults to the configuration of the project, then to text.
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct CallersArgs {
+    #[command(flatten)]
+    pub records: RecordsArgs,
+
+    // The path of the function, or its last segments, e.g. `DeviceInner::putc`
+    pub function: String,
+
+    // Defaults to the trace depth of the configuration, then to the direct callers.
+    #[arg(short, long)]
+    pub depth: Option<usize>,
+
+    // Defaults to the configuration of the project, then to text.
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    #[command(flatten)]
+    pub records: RecordsArgs,
+
+    // The records of the earlier analysis
+    #[arg(value_name = "BASELINE_DIR")]
+    pub baseline: PathBuf,
+
+    // Defaults to the configuration of the project, then to text.
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    #[command(flatten)]
+    pub records: RecordsArgs,
+
+    #[arg(long, value_name = "N")]
+    pub max_functions: Option<usize>,
+
+    #[arg(long, value_name = "N")]
+    pub max_blocks: Option<usize>,
+
+    #[arg(long, value_name = "N")]
+    pub max_impls: Option<usize>,
+
+    #[arg(long, value_name = "N")]
+    pub max_traits: Option<usize>,
+
+    // Fails on any uninitialized memory finding.
+    #[arg(long)]
+    pub deny_uninit: bool,
+
+    // Fails on any call to the function, e.g. `core::mem::transmute`.
+    #[arg(long = "deny-api", value_name = "PATH")]
+    pub deny_apis: Vec<String>,
+
+    // Defaults to the configuration of the project, then to text.
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    pub records: RecordsArgs,
+
+    // Defaults to json.
+    #[arg(long, value_enum)]
+    pub format: Option<ExportFormat>,
+
+    // Defaults to stdout.
+    #[arg(long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    // The records of an earlier analysis, only the new results are exported in sarif.
+    #[arg(long, value_name = "BASELINE_DIR")]
+    pub baseline: Option<PathBuf>,
+
+    // Only the functions reachable from the function, in dot and mermaid
+    #[arg(long, value_name = "FUNCTION")]
+    pub from: Option<String>,
+
+    // Only the functions reaching the function, in dot and mermaid
+    #[arg(long, value_name = "FUNCTION")]
+    pub to: Option<String>,
+
+    // Shows a node per module or crate instead of per function, in dot and mermaid.
+    #[arg(long, value_enum)]
+    pub collapse: Option<Collapse>,
+}
+
+// How the reports are printed, see `doc/report-schema.md` for json
+#[derive(clap::ValueEnum, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(clap::ValueEnum, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportFormat {
+    Json,
+    // SARIF 2.1.0, for code scanning
+    Sarif,
+    // a single page, browsed offline
+    Html,
+    // the call graph as a Graphviz digraph
+    Dot,
+    // the call graph as a Mermaid flowchart
+    Mermaid,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum Collapse {
+    Module,
+    Crate,
+}
+
+// Selection flags forwarded to cargo
+#[derive(Args, Debug, Default, Clone)]
+pub struct CargoArgs {
+    #[arg(short, long = "package", value_name = "SPEC")]
+    pub packages: Vec<String>,
+
+    #[arg(long)]
+    pub workspace: bool,
+
+    #[arg(short = 'F', long)]
+    pub features: Vec<String>,
+
+    #[arg(long)]
+    pub all_features: bool,
+
+    #[arg(long)]
+    pub no_default_features: bool,
+
+    #[arg(long, value_name = "TRIPLE")]
+    pub target: Option<String>,
+
     #[arg(short, long)]
-    pub call_trace: bool,
+    pub release: bool,
+
+    // `-Zbuild-std`, e.g. to analyse `core` and `alloc` of a `no_std` target
+    #[arg(long, value_name = "CRATES", num_args = 0..=1, default_missing_value = "core,alloc")]
+    pub build_std: Option<String>,
+
+    #[arg(long, value_name = "PATH")]
+    pub manifest_path: Option<PathBuf>,
+}
+
+impl CargoArgs {
+    // Flags which also change the dependency graph reported by `cargo metadata`
+    pub fn metadata_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for features in &self.features {
+            args.push("--features".to_string());
+            args.push(features.clone());
+        }
+        if self.all_features {
+            args.push("--all-features".to_string());
+        }
+        if self.no_default_features {
+            args.push("--no-default-features".to_string());
+        }
+        if let Some(manifest_path) = &self.manifest_path {
+            args.push("--manifest-path".to_string());
+            args.push(manifest_path.display().to_string());
+        }
+        args
+    }
+
+    pub fn build_args(&self) -> Vec<String> {
+        let mut args = self.metadata_args();
+        for package in &self.packages {
+            args.push("--package".to_string());
+            args.push(package.clone());
+        }
+        if self.workspace {
+            args.push("--workspace".to_string());
+        }
+        if let Some(target) = &self.target {
+            args.push("--target".to_string());
+            args.push(target.clone());
+        }
+        if self.release {
+            args.push("--release".to_string());
+        }
+        if let Some(crates) = &self.build_std {
+            args.push(format!("-Zbuild-std={}", crates));
+        }
+        args
+    }
 }
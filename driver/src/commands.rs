@@ -0,0 +1,377 @@
+use crate::cli::{
+    AnalyzeArgs, CallersArgs, CheckArgs, Collapse, DiffArgs, ExportArgs, ExportFormat, ListArgs,
+    OutputFormat, ProjectArgs, RecordsArgs, SummaryArgs, TraceArgs,
+};
+use crate::doctor;
+use crate::exit::Exit;
+use crate::metadata;
+use crate::project::ProjectConfig;
+use crate::toolchain;
+use crate::tracer;
+use crate::utils::expand_tilde;
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use utrace_common::json::{self, Check, Report};
+use utrace_common::report::{
+    print_api_calls, print_call_trace, print_callers, print_diff, print_findings, print_summary,
+    print_unsafe_list, selected,
+};
+use utrace_common::{diagram, html, sarif};
+use utrace_common::{NodeId, Records};
+
+pub fn doctor(args: ProjectArgs) {
+    if !doctor::run(out_dir(&args)) {
+        Exit::BrokenInstall.exit();
+    }
+}
+
+pub fn analyze(args: AnalyzeArgs) {
+    // The project of `--manifest-path`, or of the current directory
+    let path = args
+        .cargo
+        .manifest_path
+        .as_ref()
+        .and_then(|path| path.parent())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    // before the tracer changes to the project directory
+    let config = ProjectConfig::load(args.cargo.manifest_path.as_deref());
+    let format = args.format.or(config.format).unwrap_or_default();
+    let options = tracer::Options {
+        out_dir: args.out_dir,
+        all_targets: args.all_targets,
+        clean: args.clean,
+        workspace_only: args.workspace_only,
+        cargo: args.cargo,
+    };
+    let build = tracer::run(&path, options);
+    if !build.unanalysed.is_empty() {
+        eprintln!("Not analysed, run `analyze --clean` to analyse them again:");
+        for unit in &build.unanalysed {
+            eprintln!("- {}", unit);
+        }
+    }
+    let exit = build.exit();
+    if exit == Some(Exit::BuildFailed) {
+        eprintln!("The build failed, the records of the project are incomplete.");
+        Exit::BuildFailed.exit();
+    }
+
+    let records = load(&build.out_dir);
+    let records = records.filter(|record| !config.exclude.contains(&record.krate));
+    match format {
+        OutputFormat::Text => print_summary(&records, &config.crates),
+        OutputFormat::Json => json::summary(&records, &config.crates).print(),
+    }
+
+    if let Some(exit) = exit {
+        exit.exit();
+    }
+}
+
+pub fn summary(args: SummaryArgs) {
+    let audit = Audit::load(&args.records);
+    match audit.format(args.format) {
+        OutputFormat::Text => print_summary(&audit.records, &audit.filter),
+        OutputFormat::Json => json::summary(&audit.records, &audit.filter).print(),
+    }
+}
+
+pub fn list(args: ListArgs) {
+    let audit = Audit::load(&args.records);
+    let apis = &audit.config.dangerous_apis;
+    // Findings and calls to the dangerous APIs are always part of the json list.
+    if audit.format(args.format) == OutputFormat::Json {
+        json::unsafe_list(&audit.records, &audit.filter, apis).print();
+        return;
+    }
+
+    print_unsafe_list(&audit.records, &audit.filter);
+
+    if args.uninit {
+        println!();
+        print_findings(&audit.records, &audit.filter);
+    }
+
+    if !apis.is_empty() {
+        println!();
+        print_api_calls(&audit.records, &audit.filter, apis);
+    }
+}
+
+// Without a filter, the functions of every crate are traced.
+pub fn trace(args: TraceArgs) {
+    let audit = Audit::load(&args.records);
+    let depth = args.depth.or(audit.config.trace.depth);
+    let selected = selected(&audit.records, &audit.filter);
+    if audit.format(args.format) == OutputFormat::Json {
+        json::call_trace(&audit.records, selected, depth).print();
+        return;
+    }
+
+    let krates: BTreeSet<_> = selected.map(|record| record.krate.as_str()).collect();
+    for krate in krates {
+        print_call_trace(&audit.records, krate, depth);
+    }
+}
+
+pub fn callers(args: CallersArgs) {
+    let audit = Audit::load(&args.records);
+    let records = &audit.records;
+    let depth = args.depth.or(audit.config.trace.depth).unwrap_or(1);
+    let ids = find_functions(records, &args.function);
+
+    match audit.format(args.format) {
+        OutputFormat::Text => {
+            for id in ids {
+                print_callers(records, id, depth);
+            }
+        }
+        OutputFormat::Json => json::callers(records, &ids, depth).print(),
+    }
+}
+
+pub fn diff(args: DiffArgs) {
+    let audit = Audit::load(&args.records);
+    let baseline = load(&expand_tilde(&args.baseline));
+    let baseline = select(baseline, &args.records, &audit.config);
+    match audit.format(args.format) {
+        OutputFormat::Text => print_diff(&baseline, &audit.records, &audit.filter),
+        OutputFormat::Json => json::diff(&baseline, &audit.records, &audit.filter).print(),
+    }
+}
+
+pub fn check(args: CheckArgs) {
+    let audit = Audit::load(&args.records);
+    let selected: Vec<_> = selected(&audit.records, &audit.filter).collect();
+
+    let mut policy = audit.config.policy();
+    policy.max_functions = args.max_functions.or(policy.max_functions);
+    policy.max_blocks = args.max_blocks.or(policy.max_blocks);
+    policy.max_impls = args.max_impls.or(policy.max_impls);
+    policy.max_traits = args.max_traits.or(policy.max_traits);
+    policy.deny_uninit |= args.deny_uninit;
+    policy
+        .deny_apis
+        .splice(0..0, args.deny_apis.iter().cloned());
+    let violations = policy.violations(&audit.records, &selected);
+
+    let passed = violations.is_empty();
+    match audit.format(args.format) {
+        OutputFormat::Text => {
+            for violation in &violations {
+                println!("{}", violation.message);
+            }
+            if passed {
+                println!("Check passed.");
+            }
+        }
+        OutputFormat::Json => Report::new("check", Check { passed, violations }).print(),
+    }
+    if !passed {
+        Exit::PolicyViolation.exit();
+    }
+}
+
+pub fn export(args: ExportArgs) {
+    let audit = Audit::load(&args.records);
+    let selected: Vec<_> = selected(&audit.records, &audit.filter).collect();
+    let export = &audit.config.export;
+
+    let format = args.format.or(export.format).unwrap_or(ExportFormat::Json);
+    let baseline = match (&args.baseline, format) {
+        (None, _) => None,
+        (Some(dir), ExportFormat::Sarif) => Some(select(
+            load(&expand_tilde(dir)),
+            &args.records,
+            &audit.config,
+        )),
+        (Some(_), _) => {
+            eprintln!("--baseline is only supported with --format sarif.");
+            Exit::InvalidUsage.exit();
+        }
+    };
+
+    let graph = matches!(format, ExportFormat::Dot | ExportFormat::Mermaid);
+    if !graph && (args.from.is_some() || args.to.is_some() || args.collapse.is_some()) {
+        eprintln!("--from, --to and --collapse are only supported with --format dot or mermaid.");
+        Exit::InvalidUsage.exit();
+    }
+    let find = |function: &Option<String>| match function {
+        Some(function) => find_functions(&audit.records, function),
+        None => Vec::new(),
+    };
+    let options = diagram::Options {
+        from: find(&args.from),
+        to: find(&args.to),
+        collapse: args.collapse.map(|collapse| match collapse {
+            Collapse::Module => diagram::Collapse::Module,
+            Collapse::Crate => diagram::Collapse::Crate,
+        }),
+    };
+
+    let writer: Box<dyn Write> = match args.output.or(export.output.clone()) {
+        Some(path) => {
+            Box::new(File::create(expand_tilde(&path)).expect("Failed to create the output."))
+        }
+        None => Box::new(io::stdout()),
+    };
+    let mut writer = BufWriter::new(writer);
+    match format {
+        ExportFormat::Json => serde_json::to_writer_pretty(&mut writer, &selected),
+        ExportFormat::Sarif => {
+            let policy = audit.config.policy();
+            let log = sarif::log(&audit.records, &selected, &policy, baseline.as_ref());
+            serde_json::to_writer_pretty(&mut writer, &log)
+        }
+        ExportFormat::Html => {
+            let source_root = metadata::workspace_root(&manifest_args(&args.records.project));
+            let page = html::report(
+                &audit.records,
+                &audit.filter,
+                &audit.config.dangerous_apis,
+                source_root.as_deref(),
+            );
+            writer
+                .write_all(page.as_bytes())
+                .map_err(serde_json::Error::io)
+        }
+        ExportFormat::Dot => {
+            let dot = diagram::dot(&audit.records, &audit.filter, &options);
+            writer
+                .write_all(dot.trim_end().as_bytes())
+                .map_err(serde_json::Error::io)
+        }
+        ExportFormat::Mermaid => {
+            let mermaid = diagram::mermaid(&audit.records, &audit.filter, &options);
+            writer
+                .write_all(mermaid.trim_end().as_bytes())
+                .map_err(serde_json::Error::io)
+        }
+    }
+    .expect("Failed to export the records.");
+    writeln!(writer).expect("Failed to export the records.");
+}
+
+pub fn init() {
+    if !toolchain::build() {
+        eprintln!("Failed to build the plugin.");
+        Exit::BuildFailed.exit();
+    }
+}
+
+// The records of an earlier `analyze`, the project is not built again,
+// with the configuration of the project merged into the CLI flags.
+struct Audit {
+    records: Records,
+    config: ProjectConfig,
+    // the crates of interest
+    filter: Option<Vec<String>>,
+}
+
+impl Audit {
+    fn load(args: &RecordsArgs) -> Self {
+        let config = ProjectConfig::load(args.project.manifest_path.as_deref());
+        let Some(out_dir) = out_dir(&args.project) else {
+            eprintln!("Not in a cargo project, provide --manifest-path or --out-dir.");
+            Exit::InvalidUsage.exit();
+        };
+        let records = select(load(&out_dir), args, &config);
+        let filter = args
+            .filter
+            .clone()
+            .or(config.crates.clone())
+            .map(|f| f.iter().map(|s| s.trim().to_string()).collect());
+
+        Self {
+            records,
+            config,
+            filter,
+        }
+    }
+
+    fn format(&self, format: Option<OutputFormat>) -> OutputFormat {
+        format.or(self.config.format).unwrap_or_default()
+    }
+}
+
+// An exact path, or else every node whose path ends with the given segments
+fn find_functions<'a>(records: &'a Records, function: &str) -> Vec<&'a NodeId> {
+    let suffix = format!("::{}", function);
+    let ids: Vec<_> = match records.find_node(function) {
+        Some(id) => vec![id],
+        None => records
+            .nodes()
+            .filter(|(_, name)| name.ends_with(&suffix))
+            .map(|(id, _)| id)
+            .collect(),
+    };
+    if ids.is_empty() {
+        eprintln!("No function named {}.", function);
+        Exit::InvalidUsage.exit();
+    }
+    ids
+}
+
+// `None` outside of a cargo project without `--out-dir`
+fn out_dir(project: &ProjectArgs) -> Option<PathBuf> {
+    if let Some(out_dir) = &project.out_dir {
+        return Some(expand_tilde(out_dir));
+    }
+    Some(metadata::target_dir(&manifest_args(project))?.join("utrace"))
+}
+
+fn manifest_args(project: &ProjectArgs) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(manifest_path) = &project.manifest_path {
+        args.push("--manifest-path".to_string());
+        args.push(expand_tilde(manifest_path).display().to_string());
+    }
+    args
+}
+
+fn load(out_dir: &Path) -> Records {
+    let records = match Records::load_from(out_dir) {
+        Ok(records) if records.crates().next().is_some() => records,
+        Ok(_) => {
+            eprintln!(
+                "No records in {}, run `utrace_driver analyze` first.",
+                out_dir.display()
+            );
+            Exit::AnalysisFailed.exit();
+        }
+        Err(e) => {
+            eprintln!("Failed to read records in {}: {}", out_dir.display(), e);
+            Exit::AnalysisFailed.exit();
+        }
+    };
+    for (path, reason) in records.skipped() {
+        eprintln!("Skipped {}: {}", path, reason);
+    }
+    records
+}
+
+fn select(mut records: Records, args: &RecordsArgs, config: &ProjectConfig) -> Records {
+    if args.production_only {
+        records = records.production_only();
+    }
+    if args.runtime_only {
+        records = records.runtime_only();
+    }
+
+    let exclude: BTreeSet<_> = args
+        .exclude
+        .iter()
+        .chain(&config.exclude)
+        .map(|s| s.trim())
+        .collect();
+    if !exclude.is_empty() {
+        records = records.filter(|record| !exclude.contains(record.krate.as_str()));
+    }
+    records
+}
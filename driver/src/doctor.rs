@@ -0,0 +1,227 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+use utrace_common::config;
+
+// The components the plugin is built with, and `rust-src` for `--build-std`
+const COMPONENTS: [&str; 3] = ["rust-src", "rustc-dev", "llvm-tools"];
+
+// The problem found by a check, and how to fix it
+struct Problem {
+    problem: String,
+    fix: String,
+}
+
+impl Problem {
+    fn new(problem: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            problem: problem.into(),
+            fix: fix.into(),
+        }
+    }
+}
+
+// Returns whether every check passed.
+pub fn run(out_dir: Option<PathBuf>) -> bool {
+    let checks = [
+        ("toolchain", toolchain()),
+        ("components", components()),
+        ("sysroot lib", sysroot_lib()),
+        ("rustc", rustc_version()),
+        ("plugin", plugin()),
+        ("out dir", self::out_dir(out_dir)),
+    ];
+
+    let mut ok = true;
+    for (name, check) in checks {
+        match check {
+            Ok(detail) => println!("{:<6} {:<12} {}", "ok", name, detail),
+            Err(Problem { problem, fix }) => {
+                ok = false;
+                println!("{:<6} {:<12} {}", "fail", name, problem);
+                println!("{:<19} fix: {}", "", fix);
+            }
+        }
+    }
+    ok
+}
+
+// e.g. `nightly-2024-04-21-x86_64-unknown-linux-gnu`
+fn toolchain_name() -> String {
+    let sysroot = config::sysroot();
+    Path::new(&sysroot)
+        .file_name()
+        .map_or(sysroot.clone(), |name| name.to_string_lossy().to_string())
+}
+
+// `channel` of the `rust-toolchain.toml` of utrace
+fn pinned_channel() -> Option<String> {
+    let path = format!("{}/rust-toolchain.toml", config::root_dir());
+    let toolchain = fs::read_to_string(path).ok()?;
+    toolchain.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        (key.trim() == "channel").then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+fn toolchain() -> Result<String, Problem> {
+    let toolchain = toolchain_name();
+    let channel = pinned_channel().unwrap_or_default();
+    if !Path::new(&config::sysroot()).exists() {
+        return Err(Problem::new(
+            format!("{} is not installed", toolchain),
+            format!("rustup toolchain install {}", channel),
+        ));
+    }
+    if !toolchain.starts_with(&channel) {
+        return Err(Problem::new(
+            format!("utrace was built with {}, but pins {}", toolchain, channel),
+            "make init",
+        ));
+    }
+    Ok(toolchain)
+}
+
+fn components() -> Result<String, Problem> {
+    let path = format!("{}/lib/rustlib/components", config::sysroot());
+    let installed = fs::read_to_string(path).unwrap_or_default();
+    // e.g. `rustc-dev-x86_64-unknown-linux-gnu` or `llvm-tools-preview-x86_64-unknown-linux-gnu`
+    let missing: Vec<_> = COMPONENTS
+        .into_iter()
+        .filter(|component| {
+            !installed
+                .lines()
+                .any(|line| line == *component || line.starts_with(&format!("{}-", component)))
+        })
+        .collect();
+
+    if missing.is_empty() {
+        return Ok(COMPONENTS.join(", "));
+    }
+    let missing = missing
+        .join(" ")
+        .replace("llvm-tools", "llvm-tools-preview");
+    Err(Problem::new(
+        format!("missing {}", missing),
+        format!(
+            "rustup component add {} --toolchain {}",
+            missing,
+            toolchain_name()
+        ),
+    ))
+}
+
+// The plugin loads `rustc_driver` dynamically from here.
+fn sysroot_lib() -> Result<String, Problem> {
+    let lib = config::sysroot_lib();
+    let has_driver = fs::read_dir(&lib).into_iter().flatten().any(|entry| {
+        entry.is_ok_and(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("librustc_driver-")
+        })
+    });
+
+    if has_driver {
+        Ok(lib)
+    } else {
+        Err(Problem::new(
+            format!("no rustc_driver in {}", lib),
+            format!(
+                "rustup component add rustc --toolchain {}",
+                toolchain_name()
+            ),
+        ))
+    }
+}
+
+// The records are tagged with the rustc utrace was built with, which must be the one it runs.
+fn rustc_version() -> Result<String, Problem> {
+    let expected = env!("UTRACE_RUSTC_VERSION");
+    let output = Command::new(config::rustc_bin()).arg("-V").output();
+    let version = match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        Err(e) => {
+            return Err(Problem::new(
+                format!("cannot run {}: {}", config::rustc_bin(), e),
+                format!("rustup toolchain install {}", toolchain_name()),
+            ))
+        }
+    };
+
+    if version == expected {
+        Ok(version)
+    } else {
+        Err(Problem::new(
+            format!("utrace was built with {}, but found {}", expected, version),
+            "make init",
+        ))
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// The latest modification of the files in `dir`
+fn latest_modified(dir: &Path) -> Option<SystemTime> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                latest_modified(&path)
+            } else {
+                modified(&path)
+            }
+        })
+        .max()
+}
+
+fn plugin() -> Result<String, Problem> {
+    let plugin = PathBuf::from(config::plugin_bin());
+    let Some(built) = modified(&plugin) else {
+        return Err(Problem::new(
+            format!("{} is missing", plugin.display()),
+            "utrace_driver init",
+        ));
+    };
+
+    // The plugin also embeds the record format of `common`.
+    let root = PathBuf::from(config::root_dir());
+    let sources = ["plugin", "common"].into_iter().flat_map(|krate| {
+        [
+            latest_modified(&root.join(krate).join("src")),
+            modified(&root.join(krate).join("Cargo.toml")),
+        ]
+    });
+    if sources.flatten().any(|source| source > built) {
+        return Err(Problem::new(
+            format!("{} is older than its source", plugin.display()),
+            "utrace_driver init",
+        ));
+    }
+    Ok(plugin.display().to_string())
+}
+
+// `None` outside of a cargo project without `--out-dir`
+fn out_dir(out_dir: Option<PathBuf>) -> Result<String, Problem> {
+    let Some(out_dir) = out_dir else {
+        return Ok("skipped, not in a cargo project".to_string());
+    };
+
+    let probe = out_dir.join(".utrace-doctor");
+    let writable = fs::create_dir_all(&out_dir)
+        .and_then(|_| fs::write(&probe, ""))
+        .and_then(|_| fs::remove_file(&probe));
+    match writable {
+        Ok(_) => Ok(out_dir.display().to_string()),
+        Err(e) => Err(Problem::new(
+            format!("cannot write to {}: {}", out_dir.display(), e),
+            "choose another directory with --out-dir or UTRACE_OUT_DIR",
+        )),
+    }
+}
@@ -0,0 +1,21 @@
+use std::process;
+
+// The exit codes of utrace. 2 is also used by clap for invalid arguments.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exit {
+    // `check` found unsafe code beyond the given limits.
+    PolicyViolation = 1,
+    InvalidUsage = 2,
+    // The project, or the plugin, does not build.
+    BuildFailed = 3,
+    // Crates were built without a record, or the records cannot be read.
+    AnalysisFailed = 4,
+    // utrace is not installed correctly, see `doctor`.
+    BrokenInstall = 5,
+}
+
+impl Exit {
+    pub fn exit(self) -> ! {
+        process::exit(self as i32)
+    }
+}
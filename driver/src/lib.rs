@@ -0,0 +1,27 @@
+mod cache;
+pub mod cli;
+mod commands;
+mod doctor;
+mod exit;
+mod metadata;
+mod project;
+mod toolchain;
+mod tracer;
+mod utils;
+
+use crate::cli::{Cli, Command};
+
+pub fn run(args: Cli) {
+    match args.command {
+        Command::Init => commands::init(),
+        Command::Doctor(args) => commands::doctor(args),
+        Command::Analyze(args) => commands::analyze(args),
+        Command::Summary(args) => commands::summary(args),
+        Command::List(args) => commands::list(args),
+        Command::Trace(args) => commands::trace(args),
+        Command::Callers(args) => commands::callers(args),
+        Command::Diff(args) => commands::diff(args),
+        Command::Check(args) => commands::check(args),
+        Command::Export(args) => commands::export(args),
+    }
+}
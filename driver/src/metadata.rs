@@ -0,0 +1,187 @@
+use crate::exit::Exit;
+
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use utrace_common::config;
+use utrace_common::CrateKind;
+
+// `args` are the feature and manifest flags given to utrace.
+// `None` outside of a cargo project, any other error of cargo (e.g. a broken manifest) is fatal.
+fn metadata(args: &[String], no_deps: bool) -> Option<Value> {
+    let manifest_path = args.iter().any(|arg| arg == "--manifest-path");
+    let cwd = env::current_dir().ok()?;
+    if !manifest_path && !cwd.ancestors().any(|dir| dir.join("Cargo.toml").is_file()) {
+        return None;
+    }
+
+    let mut cmd = Command::new(config::cargo_bin());
+    cmd.arg("metadata").arg("--format-version").arg("1");
+    cmd.args(args);
+    if no_deps {
+        cmd.arg("--no-deps");
+    }
+    let output = cmd.output().expect("Failed to run cargo metadata.");
+    if !output.status.success() {
+        io::stderr().write_all(&output.stderr).unwrap();
+        eprintln!("Failed to get the package metadata.");
+        Exit::InvalidUsage.exit();
+    }
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+// `build.target` of the cargo configuration of the project, if any
+pub fn build_target() -> Option<String> {
+    let output = Command::new(config::cargo_bin())
+        .arg("-Zunstable-options")
+        .arg("config")
+        .arg("get")
+        .arg("build.target")
+        .arg("--format")
+        .arg("json-value")
+        .output()
+        .ok()?;
+    let target: Value = serde_json::from_slice(&output.stdout).ok()?;
+    // Only the first one of several targets is used to tell apart host crates.
+    match target {
+        Value::Array(targets) => targets.first()?.as_str().map(str::to_string),
+        target => target.as_str().map(str::to_string),
+    }
+}
+
+// The target directory of the project, which honours `CARGO_TARGET_DIR` and workspaces.
+// `None` outside of a cargo project.
+pub fn target_dir(args: &[String]) -> Option<PathBuf> {
+    let metadata = metadata(args, true)?;
+    metadata["target_directory"].as_str().map(PathBuf::from)
+}
+
+// The root of the workspace, which the paths of the spans of its members are relative to
+pub fn workspace_root(args: &[String]) -> Option<PathBuf> {
+    let metadata = metadata(args, true)?;
+    metadata["workspace_root"].as_str().map(PathBuf::from)
+}
+
+// The metadata of the workspace without its dependencies
+pub fn workspace(args: &[String]) -> Option<Value> {
+    metadata(args, true)
+}
+
+// The package ids of the workspace members
+pub fn workspace_members(args: &[String]) -> BTreeSet<String> {
+    let metadata = metadata(args, true).expect("Failed to get the package metadata.");
+    metadata["workspace_members"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(str::to_string)
+        .collect()
+}
+
+// The kinds of the packages which are not linked into the target, by manifest directory:
+// the packages only reachable through build-dependencies or proc-macros,
+// and those only reachable through the dev-dependencies of the workspace members.
+// Cargo builds the dev-dependencies of the workspace members only, for their tests.
+pub fn package_kinds(args: &[String]) -> BTreeMap<PathBuf, CrateKind> {
+    let metadata = metadata(args, false).expect("Failed to get the package metadata.");
+
+    let mut packages = BTreeMap::new();
+    for package in metadata["packages"].as_array().into_iter().flatten() {
+        let id = package["id"].as_str().unwrap_or_default();
+        let manifest_dir = package["manifest_path"]
+            .as_str()
+            .map(|path| {
+                Path::new(path)
+                    .parent()
+                    .unwrap_or(Path::new(path))
+                    .to_path_buf()
+            })
+            .unwrap_or_default();
+        let proc_macro = package["targets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|target| target["kind"].as_array().into_iter().flatten())
+            .any(|kind| kind == "proc-macro");
+        packages.insert(id, (manifest_dir, proc_macro));
+    }
+
+    let mut deps = BTreeMap::new();
+    let mut dev_deps = BTreeMap::new();
+    for node in metadata["resolve"]["nodes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        let id = node["id"].as_str().unwrap_or_default();
+        // `None` for normal dependencies
+        let of_kind = |dep_kind: Option<&str>| -> Vec<&str> {
+            node["deps"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|dep| {
+                    dep["dep_kinds"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .any(|kind| kind["kind"].as_str() == dep_kind)
+                })
+                .filter_map(|dep| dep["pkg"].as_str())
+                .collect()
+        };
+        deps.insert(id, of_kind(None));
+        dev_deps.insert(id, of_kind(Some("dev")));
+    }
+
+    let members: Vec<_> = metadata["workspace_members"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    // Proc-macros only run on the host, as well as their dependencies.
+    let proc_macro = |id: &str| packages.get(id).map(|(_, p)| *p).unwrap_or(false);
+    let runtime = reachable(members.clone(), &deps, proc_macro);
+    let dev_roots = members
+        .iter()
+        .flat_map(|id| dev_deps.get(id).into_iter().flatten().copied())
+        .collect();
+    let dev = reachable(dev_roots, &deps, |id| {
+        proc_macro(id) || runtime.contains(id)
+    });
+
+    packages
+        .iter()
+        .filter(|(id, _)| !runtime.contains(*id))
+        .map(|(id, (manifest_dir, _))| {
+            let kind = if dev.contains(*id) {
+                CrateKind::DevDependency
+            } else {
+                CrateKind::BuildDependency
+            };
+            (manifest_dir.clone(), kind)
+        })
+        .collect()
+}
+
+// The packages reachable from `roots` through normal dependencies, without going through `skip`
+fn reachable<'a>(
+    roots: Vec<&'a str>,
+    deps: &BTreeMap<&'a str, Vec<&'a str>>,
+    skip: impl Fn(&str) -> bool,
+) -> BTreeSet<&'a str> {
+    let mut found = BTreeSet::new();
+    let mut queue = roots;
+    while let Some(id) = queue.pop() {
+        if skip(id) || !found.insert(id) {
+            continue;
+        }
+        queue.extend(deps.get(id).into_iter().flatten());
+    }
+    found
+}
@@ -0,0 +1,238 @@
+use crate::cli::{ExportFormat, OutputFormat};
+use crate::exit::Exit;
+use crate::metadata;
+use crate::utils::expand_tilde;
+
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use utrace_common::check::Policy;
+
+// The audit setup committed next to the code, in `utrace.toml`
+// or in `[package.metadata.utrace]`/`[workspace.metadata.utrace]` of `Cargo.toml`.
+// CLI flags take precedence over it.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProjectConfig {
+    // the crates of interest, i.e. the default `--filter`
+    pub crates: Option<Vec<String>>,
+    pub exclude: Vec<String>,
+    // paths of functions whose calls are listed and denied by `check`, e.g. `core::mem::transmute`
+    pub dangerous_apis: Vec<String>,
+    // the default `--format` of the reports
+    pub format: Option<OutputFormat>,
+    pub check: CheckConfig,
+    pub trace: TraceConfig,
+    pub export: ExportConfig,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CheckConfig {
+    pub max_functions: Option<usize>,
+    pub max_blocks: Option<usize>,
+    pub max_impls: Option<usize>,
+    pub max_traits: Option<usize>,
+    pub deny_uninit: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TraceConfig {
+    pub depth: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ExportConfig {
+    pub format: Option<ExportFormat>,
+    // relative to the project
+    pub output: Option<PathBuf>,
+}
+
+impl ProjectConfig {
+    // The first one found of `utrace.toml` of the package, `utrace.toml` of the workspace,
+    // `[package.metadata.utrace]` and `[workspace.metadata.utrace]`.
+    // Outside of a cargo project, the default configuration is used.
+    pub fn load(manifest_path: Option<&Path>) -> Self {
+        // Like cargo, the manifest of the current directory or of one of its parents
+        let manifest_path = match manifest_path {
+            // as cargo reports it, e.g. without `..`
+            Some(path) => {
+                let path = env::current_dir().unwrap().join(expand_tilde(path));
+                fs::canonicalize(&path).unwrap_or(path)
+            }
+            None => {
+                let cwd = env::current_dir().unwrap();
+                let Some(dir) = cwd.ancestors().find(|dir| dir.join("Cargo.toml").is_file()) else {
+                    return Self::default();
+                };
+                dir.join("Cargo.toml")
+            }
+        };
+        let args = vec![
+            "--manifest-path".to_string(),
+            manifest_path.display().to_string(),
+        ];
+        let Some(metadata) = metadata::workspace(&args) else {
+            return Self::default();
+        };
+
+        let package_dir = manifest_path.parent().unwrap_or(&manifest_path);
+        let workspace_root = metadata["workspace_root"].as_str().map(PathBuf::from);
+        let files = [Some(package_dir.to_path_buf()), workspace_root.clone()];
+        for dir in files.into_iter().flatten() {
+            let path = dir.join("utrace.toml");
+            if let Ok(text) = fs::read_to_string(&path) {
+                return Self::parse(
+                    &path,
+                    basic_toml::from_str(&text).map_err(|e| e.to_string()),
+                )
+                .with_base(&dir);
+            }
+        }
+
+        let package = metadata["packages"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|package| package["manifest_path"].as_str() == manifest_path.to_str());
+        let tables = [
+            package.map(|package| (&package["metadata"]["utrace"], package_dir.to_path_buf())),
+            workspace_root.map(|root| (&metadata["metadata"]["utrace"], root)),
+        ];
+        for (table, dir) in tables.into_iter().flatten() {
+            if !table.is_null() {
+                let path = dir.join("Cargo.toml");
+                return Self::parse(
+                    &path,
+                    serde_json::from_value(table.clone()).map_err(|e| e.to_string()),
+                )
+                .with_base(&dir);
+            }
+        }
+
+        Self::default()
+    }
+
+    fn parse(path: &Path, config: Result<Self, String>) -> Self {
+        config.unwrap_or_else(|e| {
+            eprintln!("Invalid utrace configuration in {}: {}", path.display(), e);
+            Exit::InvalidUsage.exit();
+        })
+    }
+
+    // The policy of `check` without its flags, calls to the dangerous APIs are denied.
+    pub fn policy(&self) -> Policy {
+        Policy {
+            max_functions: self.check.max_functions,
+            max_blocks: self.check.max_blocks,
+            max_impls: self.check.max_impls,
+            max_traits: self.check.max_traits,
+            deny_uninit: self.check.deny_uninit,
+            deny_apis: self.dangerous_apis.clone(),
+        }
+    }
+
+    // Paths of the configuration are relative to the file declaring them.
+    fn with_base(mut self, dir: &Path) -> Self {
+        self.export.output = self.export.output.map(|output| dir.join(output));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A package with an empty lib in a fresh directory, with `utrace.toml` if given
+    fn package(name: &str, metadata: &str, utrace_toml: Option<&str>) -> PathBuf {
+        let dir = env::temp_dir().join(format!("utrace-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/lib.rs"), "").unwrap();
+        let manifest = format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n{}",
+            name, metadata
+        );
+        fs::write(dir.join("Cargo.toml"), manifest).unwrap();
+        if let Some(text) = utrace_toml {
+            fs::write(dir.join("utrace.toml"), text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn parse() {
+        let text = r#"
+            crates = ["islet_rmm"]
+            exclude = ["log"]
+            dangerous-apis = ["core::mem::transmute"]
+            format = "json"
+
+            [check]
+            max-functions = 3
+            deny-uninit = true
+
+            [trace]
+            depth = 2
+
+            [export]
+            format = "sarif"
+            output = "utrace.sarif"
+        "#;
+        let config: ProjectConfig = basic_toml::from_str(text).unwrap();
+        assert_eq!(config.crates, Some(vec!["islet_rmm".to_string()]));
+        assert_eq!(config.exclude, ["log"]);
+        assert_eq!(config.dangerous_apis, ["core::mem::transmute"]);
+        assert_eq!(config.format, Some(OutputFormat::Json));
+        assert_eq!(config.check.max_functions, Some(3));
+        assert_eq!(config.check.max_blocks, None);
+        assert!(config.check.deny_uninit);
+        assert_eq!(config.trace.depth, Some(2));
+        assert!(matches!(config.export.format, Some(ExportFormat::Sarif)));
+
+        let config = config.with_base(Path::new("/project"));
+        assert_eq!(
+            config.export.output,
+            Some(PathBuf::from("/project/utrace.sarif"))
+        );
+    }
+
+    #[test]
+    fn parse_unknown_key() {
+        assert!(basic_toml::from_str::<ProjectConfig>("max-functions = 3").is_err());
+        assert!(basic_toml::from_str::<ProjectConfig>("format = \"sarif\"").is_err());
+    }
+
+    #[test]
+    fn utrace_toml_over_metadata() {
+        let dir = package(
+            "precedence",
+            "[package.metadata.utrace]\nexclude = [\"metadata\"]\n",
+            Some("exclude = [\"file\"]\n"),
+        );
+        let config = ProjectConfig::load(Some(&dir.join("Cargo.toml")));
+        assert_eq!(config.exclude, ["file"]);
+
+        fs::remove_file(dir.join("utrace.toml")).unwrap();
+        let config = ProjectConfig::load(Some(&dir.join("Cargo.toml")));
+        assert_eq!(config.exclude, ["metadata"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn metadata_of_relative_manifest_path() {
+        let dir = package(
+            "relative",
+            "[package.metadata.utrace]\nformat = \"json\"\n\n[package.metadata.utrace.export]\noutput = \"out.json\"\n",
+            None,
+        );
+        let config = ProjectConfig::load(Some(&dir.join("src/../Cargo.toml")));
+        assert_eq!(config.format, Some(OutputFormat::Json));
+        let output = fs::canonicalize(&dir).unwrap().join("out.json");
+        assert_eq!(config.export.output, Some(output));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,59 +1,26 @@
-use crate::utils::copy_dir;
-
 use std::env;
-use std::fs;
 use std::path::Path;
 use std::process::Command;
 use utrace_common::config;
 
-pub fn build() {
+// Returns whether the plugin was built.
+pub fn build() -> bool {
     let plugin_dir = config::plugin_dir();
     let plugin_dir = Path::new(&plugin_dir);
     env::set_current_dir(plugin_dir).expect("Failed to change dir to plugin.");
 
-    Command::new("cargo")
+    Command::new(config::cargo_bin())
         .arg("build")
         .arg("--release")
         .status()
-        .expect("Failed to build plugin.");
+        .expect("Failed to build plugin.")
+        .success()
 }
 
-pub fn install() {
-    // bin
-    let toolchain_dir = config::toolchain_dir();
-    let bin_dir = Path::new(&toolchain_dir).join("bin");
-    if !bin_dir.exists() {
-        fs::create_dir_all(&bin_dir).expect("Failed to create toolchain/bin dir.");
+// The plugin loads `rustc_driver` from the sysroot it is built with.
+pub fn library_path() -> String {
+    match env::var("LD_LIBRARY_PATH") {
+        Ok(path) if !path.is_empty() => format!("{}:{}", config::sysroot_lib(), path),
+        _ => config::sysroot_lib(),
     }
-
-    let plugin_bin = config::plugin_bin();
-    let plugin_bin = Path::new(&plugin_bin);
-    let target_bin = Path::new(&bin_dir).join("rustc");
-
-    fs::copy(plugin_bin, target_bin).expect("Failed to copy a plugin.");
-
-    // lib
-    let sysroot = Command::new("rustc")
-        .arg("--print")
-        .arg("sysroot")
-        .output()
-        .expect("Failed to get sysroot")
-        .stdout;
-    let sysroot_lib = Path::new(String::from_utf8_lossy(&sysroot).trim()).join("lib");
-
-    let lib_dir = Path::new(&toolchain_dir).join("lib");
-    if !lib_dir.exists() {
-        fs::create_dir_all(&bin_dir).expect("Failed to create toolchain/lib dir.");
-    }
-
-    copy_dir(sysroot_lib, lib_dir).unwrap();
-
-    // link
-    Command::new("rustup")
-        .arg("toolchain")
-        .arg("link")
-        .arg("utrace")
-        .arg(&toolchain_dir)
-        .status()
-        .expect("Failed to link toolchain");
 }
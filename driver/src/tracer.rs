@@ -1,34 +1,175 @@
+use crate::cache::{self, Units};
+use crate::cli::CargoArgs;
+use crate::exit::Exit;
+use crate::metadata;
+use crate::toolchain;
 use crate::utils::expand_tilde;
 
 use std::env;
 use std::fs;
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use utrace_common::config;
+use utrace_common::CrateKind;
+
+pub struct Options {
+    pub out_dir: Option<PathBuf>,
+    pub all_targets: bool,
+    // Analyses every crate again instead of only the ones cargo recompiles.
+    pub clean: bool,
+    pub workspace_only: bool,
+    pub cargo: CargoArgs,
+}
+
+pub struct Build {
+    // where the records are written to
+    pub out_dir: PathBuf,
+    pub success: bool,
+    // the compilations of the build which have no record
+    pub unanalysed: Vec<String>,
+}
+
+impl Build {
+    // A failed build leaves crates without a record, too, so it takes precedence.
+    pub fn exit(&self) -> Option<Exit> {
+        if !self.success {
+            Some(Exit::BuildFailed)
+        } else if !self.unanalysed.is_empty() {
+            Some(Exit::AnalysisFailed)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn run(path: &Path, mut options: Options) -> Build {
+    // Relative paths are relative to where utrace is run, not to the project.
+    let absolute = |dir: PathBuf| env::current_dir().unwrap().join(expand_tilde(&dir));
+    let out_dir = options.out_dir.map(absolute);
+    options.cargo.manifest_path = options.cargo.manifest_path.map(absolute);
 
-pub fn run(path: &Path) {
     let target_dir = expand_tilde(path);
-    let target_dir = fs::canonicalize(target_dir).expect("Failed to get the absosulte path.");
-    env::set_current_dir(target_dir).expect("Failed to change dir to plugin.");
-
-    let out_dir = utrace_common::config::out_dir();
-    let path = Path::new(&out_dir);
-    if path.exists() {
-        fs::remove_dir_all(path).unwrap();
-        fs::create_dir_all(path).unwrap();
-    } else {
-        fs::create_dir_all(path).unwrap();
+    if let Err(e) = fs::canonicalize(&target_dir).and_then(env::set_current_dir) {
+        eprintln!("Failed to enter {}: {}", target_dir.display(), e);
+        Exit::InvalidUsage.exit();
+    }
+
+    let metadata_args = options.cargo.metadata_args();
+    let out_dir = out_dir.unwrap_or_else(|| match metadata::target_dir(&metadata_args) {
+        Some(target_dir) => target_dir.join("utrace"),
+        None => {
+            eprintln!("Not in a cargo project, provide --manifest-path.");
+            Exit::InvalidUsage.exit();
+        }
+    });
+    let package_kinds = metadata::package_kinds(&metadata_args);
+    let packages = |kind: CrateKind| {
+        package_kinds
+            .iter()
+            .filter(|(_, k)| **k == kind)
+            .map(|(dir, _)| dir.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let host_packages = packages(CrateKind::BuildDependency);
+    let dev_packages = packages(CrateKind::DevDependency);
+
+    // The project's own `.cargo/config.toml` is honoured by cargo as it runs in the project.
+    let build_target = options.cargo.target.clone().or_else(metadata::build_target);
+    // Cargo builds the standard library only for an explicit target.
+    if options.cargo.build_std.is_some() && build_target.is_none() {
+        eprintln!("--build-std requires --target or `build.target` in the cargo config.");
+        Exit::InvalidUsage.exit();
     }
 
-    Command::new("cargo")
-        .arg("clean")
-        .status()
-        .expect("Failed to clean the package.");
+    let settings = format!(
+        "workspace only: {}\nbuild target: {}\nbuild std: {}\nhost packages: {}\ndev packages: {}\n",
+        options.workspace_only,
+        build_target.as_deref().unwrap_or_default(),
+        options.cargo.build_std.as_deref().unwrap_or_default(),
+        host_packages,
+        dev_packages
+    );
+    let build_dir = cache::prepare(&out_dir, options.clean, &settings);
 
-    Command::new("rustup")
-        .arg("run")
-        .arg("utrace")
-        .arg("cargo")
-        .arg("build")
-        .status()
+    // The plugin wraps the rustc of its own toolchain, whichever toolchain the project pins,
+    // since it can only read the metadata of crates compiled by the same rustc.
+    // Other wrappers (e.g. sccache) would skip the plugin for cached crates.
+    let wrapper = if options.workspace_only {
+        "RUSTC_WORKSPACE_WRAPPER"
+    } else {
+        "RUSTC_WRAPPER"
+    };
+    let mut cmd = Command::new(config::cargo_bin());
+    cmd.arg("build");
+    cmd.arg("--message-format=json-render-diagnostics");
+    cmd.env_remove("RUSTC_WRAPPER");
+    cmd.env_remove("RUSTC_WORKSPACE_WRAPPER");
+    cmd.env(wrapper, config::plugin_bin());
+    cmd.env("RUSTC", config::rustc_bin());
+    cmd.env("LD_LIBRARY_PATH", toolchain::library_path());
+    cmd.env("CARGO_TARGET_DIR", build_dir);
+    cmd.env("UTRACE_HOST_PACKAGES", &host_packages);
+    cmd.env("UTRACE_DEV_PACKAGES", &dev_packages);
+    cmd.env("UTRACE_OUT_DIR", &out_dir);
+    if options.all_targets {
+        cmd.arg("--all-targets");
+    }
+    cmd.args(options.cargo.build_args());
+    if let Some(target) = build_target {
+        cmd.env("UTRACE_BUILD_TARGET", target);
+    }
+    let output = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .output()
         .expect("Failed to utrace.");
+
+    // A failed build does not list all compilations, so nothing is pruned.
+    let units = Units::parse(&output.stdout);
+    if output.status.success() {
+        cache::prune(&out_dir, &units);
+    }
+
+    // Without the wrapper, rustc compiles the other crates.
+    let members = metadata::workspace_members(&metadata_args);
+    let unanalysed = cache::unanalysed(&out_dir, &units)
+        .into_iter()
+        .filter(|unit| !options.workspace_only || members.contains(&unit.package_id))
+        .map(|unit| unit.to_string())
+        .collect();
+
+    Build {
+        out_dir,
+        success: output.status.success(),
+        unanalysed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(success: bool, unanalysed: &[&str]) -> Build {
+        Build {
+            out_dir: PathBuf::from("target/utrace"),
+            success,
+            unanalysed: unanalysed.iter().map(|unit| unit.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(build(true, &[]).exit(), None);
+        // e.g. the plugin crashed on a crate, which rustc still compiled
+        assert_eq!(
+            build(true, &["toy 0.1.0 (lib)"]).exit(),
+            Some(Exit::AnalysisFailed)
+        );
+        assert_eq!(build(false, &[]).exit(), Some(Exit::BuildFailed));
+        assert_eq!(
+            build(false, &["toy 0.1.0 (lib)"]).exit(),
+            Some(Exit::BuildFailed)
+        );
+    }
 }
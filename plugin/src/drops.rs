@@ -0,0 +1,102 @@
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_middle::mir::{Body, TerminatorKind};
+use rustc_middle::ty::{self, ParamEnv, Ty, TyCtxt};
+use rustc_span::Span;
+
+// Returns the `Drop::drop` impls run by scope-end drops and `drop_in_place` calls in a body,
+// together with the span of the drop.
+pub fn drop_calls(tcx: TyCtxt<'_>, def_id: LocalDefId) -> Vec<(DefId, Span)> {
+    let mut calls = Vec::new();
+    if !tcx.is_mir_available(def_id) {
+        return calls;
+    }
+
+    // The MIR before optimisations, as the inliner moves the drops of callees into the caller.
+    // It is stolen by `optimized_mir`, which only runs after the analysis,
+    // except for coroutines whose layout needs it, but nothing is inlined into coroutines.
+    let elaborated;
+    let body: &Body<'_> = if tcx.is_coroutine(def_id.to_def_id()) {
+        tcx.optimized_mir(def_id)
+    } else {
+        elaborated = tcx.mir_drops_elaborated_and_const_checked(def_id).borrow();
+        &elaborated
+    };
+    let param_env = tcx.param_env(def_id);
+    let drop_in_place = tcx.lang_items().drop_in_place_fn();
+
+    for block in body.basic_blocks.iter() {
+        // Unwind paths only repeat the drops of the normal path.
+        if block.is_cleanup {
+            continue;
+        }
+
+        let terminator = block.terminator();
+        let ty = match &terminator.kind {
+            TerminatorKind::Drop { place, .. } => place.ty(body, tcx).ty,
+            TerminatorKind::Call { func, .. } => match func.const_fn_def() {
+                Some((callee, args)) if Some(callee) == drop_in_place => args.type_at(0),
+                _ => continue,
+            },
+            _ => continue,
+        };
+
+        let mut visited = Vec::new();
+        let mut dtors = Vec::new();
+        collect_dtors(tcx, param_env, ty, &mut visited, &mut dtors);
+        calls.extend(
+            dtors
+                .into_iter()
+                .map(|dtor| (dtor, terminator.source_info.span)),
+        );
+    }
+
+    calls
+}
+
+// Follows the drop glue of `ty`: its own `Drop` impl, then its fields.
+// Types with a `Drop` impl are assumed to drop the values of their type parameters (e.g. `Vec<T>`).
+fn collect_dtors<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    param_env: ParamEnv<'tcx>,
+    ty: Ty<'tcx>,
+    visited: &mut Vec<Ty<'tcx>>,
+    dtors: &mut Vec<DefId>,
+) {
+    if visited.contains(&ty) || !ty.needs_drop(tcx, param_env) {
+        return;
+    }
+    visited.push(ty);
+
+    match ty.kind() {
+        ty::Adt(adt, args) => {
+            if adt.is_manually_drop() {
+                return;
+            }
+
+            if let Some(dtor) = tcx.adt_destructor(adt.did()) {
+                dtors.push(dtor.did);
+                for arg in args.types() {
+                    collect_dtors(tcx, param_env, arg, visited, dtors);
+                }
+            }
+
+            for field in adt.all_fields() {
+                collect_dtors(tcx, param_env, field.ty(tcx, args), visited, dtors);
+            }
+        }
+        ty::Tuple(tys) => {
+            for ty in tys.iter() {
+                collect_dtors(tcx, param_env, ty, visited, dtors);
+            }
+        }
+        ty::Array(ty, _) | ty::Slice(ty) => {
+            collect_dtors(tcx, param_env, *ty, visited, dtors);
+        }
+        ty::Closure(_, args) => {
+            for ty in args.as_closure().upvar_tys() {
+                collect_dtors(tcx, param_env, ty, visited, dtors);
+            }
+        }
+        _ => {}
+    }
+}
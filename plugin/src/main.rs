@@ -4,14 +4,20 @@ extern crate rustc_driver;
 extern crate rustc_hir;
 extern crate rustc_interface;
 extern crate rustc_middle;
+extern crate rustc_session;
 extern crate rustc_span;
 
+mod drops;
 mod parser;
+mod uninit;
 
 use rustc_driver::{Callbacks, Compilation};
 use rustc_interface::{interface::Compiler, Queries};
+use rustc_span::def_id::LOCAL_CRATE;
 
 use crate::parser::Parser;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
 
 struct Plugin;
 
@@ -23,8 +29,22 @@ impl Callbacks for Plugin {
     ) -> Compilation {
         queries.global_ctxt().unwrap().enter(|tcx| {
             let mut parser = Parser::new(tcx);
-            parser.run();
-            parser.save();
+            if parser.is_fresh() {
+                return;
+            }
+
+            // A crash of the analysis leaves only this crate without a record,
+            // which the driver reports, instead of failing the whole build.
+            // The crash may leave `tcx` inconsistent, so the analysis does not use it again.
+            let krate = tcx.crate_name(LOCAL_CRATE);
+            let analysis = panic::catch_unwind(AssertUnwindSafe(|| {
+                parser.run();
+                parser.save();
+            }));
+            if analysis.is_err() {
+                parser.discard();
+                eprintln!("utrace: failed to analyse {}", krate);
+            }
         });
 
         Compilation::Continue
@@ -32,14 +52,35 @@ impl Callbacks for Plugin {
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    rustc_driver::RunCompiler::new(&args, &mut Plugin)
-        .run()
-        .unwrap();
+    let mut args: Vec<String> = std::env::args().collect();
+
+    // As `RUSTC_WRAPPER`, cargo passes the path of rustc first.
+    // The plugin compiles with its own rustc, which is the one the driver sets as `RUSTC`.
+    let wrapped = args
+        .get(1)
+        .and_then(|arg| Path::new(arg).file_stem())
+        .is_some_and(|stem| stem == "rustc");
+    if wrapped {
+        args.remove(1);
+    }
+    // Compilation errors are reported by rustc, and its exit code is passed on to cargo.
+    let exit_code = rustc_driver::catch_with_exit_code(|| {
+        rustc_driver::RunCompiler::new(&args, &mut Plugin).run()
+    });
 
     #[cfg(debug_assertions)]
     {
-        use utrace_common::report;
-        report(Some(vec!["unsafe_keyword".to_string()]), true, true);
+        use utrace_common::{config, report};
+        report(
+            Path::new(&config::out_dir()),
+            Some(vec!["unsafe_keyword".to_string()]),
+            false,
+            false,
+            true,
+            true,
+            true,
+        );
     }
+
+    std::process::exit(exit_code);
 }
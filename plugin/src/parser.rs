@@ -1,28 +1,62 @@
-use utrace_common::{Record, UnsafeKind};
+use crate::drops;
+use crate::uninit;
 
-use rustc_hir::def_id::LocalDefId;
+use utrace_common::config;
+use utrace_common::{
+    fnv1a, CallKind, CallSite, CrateKind, DiagnosticKind, Fingerprint, NodeId, OwnerKind,
+    PackageId, Record, SourceSpan, TargetKind, UnsafeKind,
+};
+
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::{DefId, LocalDefId};
 use rustc_hir::intravisit::{self, FnKind, Visitor};
 use rustc_hir::BlockCheckMode::UnsafeBlock;
 use rustc_hir::{
-    Block, BodyId, Expr, ExprKind, FnDecl, ImplItem, Item, ItemKind, QPath, TraitFn, TraitItem,
-    UnsafeSource, Unsafety,
+    AnonConst, Block, BodyId, ConstBlock, ConstContext, Expr, ExprKind, FnDecl, HirId, ImplItem,
+    Item, ItemKind, TraitFn, TraitItem, UnsafeSource, Unsafety,
 };
-use rustc_middle::ty::TyCtxt;
+use rustc_middle::ty::print::with_no_visible_paths;
+use rustc_middle::ty::{self, Ty, TyCtxt};
+use rustc_session::config::CrateType;
 use rustc_span::def_id;
-use rustc_span::Span;
+use rustc_span::{sym, Span};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
 
 pub struct Parser<'tcx> {
     tcx: TyCtxt<'tcx>,
     record: Record,
+    with_capacity: Vec<HirId>,
+    // the unsafe blocks and unsafe fn bodies around the visited expression
+    unsafe_depth: usize,
+    // spans of the unsafe blocks and unsafe fn bodies of the current item
+    unsafe_blocks: Vec<Span>,
 }
 
 impl<'tcx> Parser<'tcx> {
     pub fn new(tcx: TyCtxt<'tcx>) -> Self {
         let krate = tcx.crate_name(def_id::LOCAL_CRATE).to_string();
+        let target = target_kind(tcx);
+        let crate_kind = crate_kind(tcx, &target);
+        let target_triple = tcx.sess.opts.target_triple.triple().to_string();
+        let package = package_id(&krate);
+        let stable_crate_id = tcx.stable_crate_id(def_id::LOCAL_CRATE).as_u64();
+        let fingerprint = fingerprint(tcx);
 
         Self {
             tcx,
-            record: Record::new(krate),
+            record: Record::new(
+                krate,
+                package,
+                stable_crate_id,
+                target,
+                crate_kind,
+                target_triple,
+                fingerprint,
+            ),
+            with_capacity: Vec::new(),
+            unsafe_depth: 0,
+            unsafe_blocks: Vec::new(),
         }
     }
 
@@ -30,31 +64,409 @@ impl<'tcx> Parser<'tcx> {
         self.tcx.hir().visit_all_item_likes_in_crate(self);
     }
 
+    // Cargo also recompiles crates whose dependencies changed in ways that do not matter to them.
+    pub fn is_fresh(&self) -> bool {
+        let path = self.record.path(&config::out_dir());
+        Record::load(&path).is_ok_and(|record| record.fingerprint == self.record.fingerprint)
+    }
+
+    // A record which cannot be written is reported by the driver as a crate not analysed.
     pub fn save(&self) {
         // debug
         //        self.record.print_items_list();
         //        self.record.print_call_graph();
         //
-        self.record.save(&utrace_common::config::out_dir()).unwrap();
+        if let Err(e) = self.record.save(&config::out_dir()) {
+            eprintln!(
+                "utrace: failed to save the record of {}: {}",
+                self.record.krate, e
+            );
+        }
+    }
+
+    // Removes the record of an earlier compilation, so that a failed analysis leaves no stale record.
+    pub fn discard(&self) {
+        let _ = std::fs::remove_file(self.record.path(&config::out_dir()));
+    }
+
+    // Nodes are keyed by `DefPathHash`, which every crate computes the same way for an item.
+    // The path is kept as display data only.
+    fn node(&mut self, def_id: DefId) -> NodeId {
+        let (hi, lo) = self.tcx.def_path_hash(def_id).0.split();
+        let id = format!("{:016x}{:016x}", hi.as_u64(), lo.as_u64());
+
+        if !self.record.nodes.contains_key(&id) {
+            self.record.add_node(id.clone(), self.path(def_id));
+            self.record.add_module(id.clone(), self.module(def_id));
+        }
+        id
+    }
+
+    fn path(&self, def_id: DefId) -> String {
+        let path = with_no_visible_paths!(self.tcx.def_path_str(def_id));
+        if def_id.is_local() {
+            format!("{}::{}", self.tcx.crate_name(def_id.krate), path)
+        } else {
+            path
+        }
+    }
+
+    // The closest module around an item, since functions, impls and blocks hold items, too.
+    fn module(&self, def_id: DefId) -> String {
+        let mut module = def_id;
+        while self.tcx.def_kind(module) != DefKind::Mod {
+            match self.tcx.opt_parent(module) {
+                Some(parent) => module = parent,
+                None => break,
+            }
+        }
+        if module.is_crate_root() {
+            self.tcx.crate_name(module.krate).to_string()
+        } else {
+            self.path(module)
+        }
+    }
+
+    // Calls through fn pointers have no known callee, so the pointer type stands in for it.
+    fn fn_pointer_node(&mut self, ty: Ty<'tcx>) -> NodeId {
+        let name = ty.to_string();
+        let id = format!("fn-pointer:{}", name);
+        self.record.add_node(id.clone(), name);
+        id
+    }
+
+    // Anonymous and inline consts own their bodies, everything else is attributed to the enclosing item.
+    fn context_owner(&self, hir_id: HirId) -> (LocalDefId, OwnerKind) {
+        let body_owner = self.tcx.hir().enclosing_body_owner(hir_id);
+        match self.tcx.def_kind(body_owner) {
+            DefKind::AnonConst => (body_owner, OwnerKind::AnonConst),
+            DefKind::InlineConst => (body_owner, OwnerKind::InlineConst),
+            _ => {
+                let owner_id = self.tcx.hir().get_parent_item(hir_id).def_id;
+                (owner_id, self.owner_kind(owner_id))
+            }
+        }
+    }
+
+    fn owner_kind(&self, def_id: LocalDefId) -> OwnerKind {
+        match self.tcx.def_kind(def_id) {
+            DefKind::AnonConst => OwnerKind::AnonConst,
+            DefKind::InlineConst => OwnerKind::InlineConst,
+            DefKind::Trait => OwnerKind::Trait,
+            DefKind::Impl { .. } => OwnerKind::Impl,
+            _ => match self.tcx.hir().body_const_context(def_id) {
+                Some(ConstContext::ConstFn) => OwnerKind::ConstFn,
+                Some(ConstContext::Const { .. }) => OwnerKind::Const,
+                Some(ConstContext::Static(_)) => OwnerKind::Static,
+                None => OwnerKind::Fn,
+            },
+        }
+    }
+
+    // Only the items of a library can be called by the crates depending on it.
+    fn is_exported(&self, def_id: LocalDefId) -> bool {
+        let library = self
+            .tcx
+            .crate_types()
+            .iter()
+            .any(|crate_type| matches!(crate_type, CrateType::Rlib | CrateType::Dylib));
+        library && self.tcx.effective_visibilities(()).is_exported(def_id)
+    }
+
+    // Named consts and statics become nodes of the call graph like functions do.
+    fn add_const_context(&mut self, def_id: LocalDefId) {
+        let id = self.node(def_id.to_def_id());
+        let kind = self.owner_kind(def_id);
+        if matches!(kind, OwnerKind::Const | OwnerKind::Static) {
+            self.record.add_fn(id.clone());
+        }
+        self.record.add_const_context(id, kind);
+    }
+
+    // Bodies are visited before `visit_fn` is called for them, so their unsafe blocks are known here.
+    fn add_drop_edges(&mut self, id: LocalDefId) {
+        let caller = self.node(id.to_def_id());
+        for (dtor, span) in drops::drop_calls(self.tcx, id) {
+            let callee = self.node(dtor);
+            let site = self.drop_site(span);
+            self.record
+                .add_edge(caller.clone(), callee, CallKind::Drop, site);
+        }
+    }
+
+    // Calls in a closure are made by the closure, the other calls by the owner of the context.
+    fn caller(&mut self, hir_id: HirId) -> NodeId {
+        let body_owner = self.tcx.hir().enclosing_body_owner(hir_id);
+        let owner_id = if self.tcx.is_closure_like(body_owner.to_def_id()) {
+            body_owner
+        } else {
+            self.context_owner(hir_id).0
+        };
+        self.node(owner_id.to_def_id())
+    }
+
+    fn add_call(&mut self, hir_id: HirId, callee: NodeId, kind: CallKind, span: Span) {
+        let caller = self.caller(hir_id);
+        let site = CallSite {
+            span: self.source_span(span),
+            in_unsafe: self.unsafe_depth > 0,
+        };
+        self.record.add_edge(caller, callee, kind, site);
+    }
+
+    // The analysis goes on without the node, the gap is kept in the record instead.
+    fn add_diagnostic(&mut self, hir_id: HirId, kind: DiagnosticKind, span: Span) {
+        let owner = self.caller(hir_id);
+        let span = self.source_span(span);
+        self.record.add_diagnostic(kind, owner, span);
+    }
+
+    // Drops are only known once their body was visited, so they are looked up in its unsafe blocks.
+    fn drop_site(&self, span: Span) -> CallSite {
+        let callsite = span.source_callsite();
+        CallSite {
+            span: self.source_span(span),
+            in_unsafe: self
+                .unsafe_blocks
+                .iter()
+                .any(|block| block.contains(callsite)),
+        }
+    }
+
+    // The body of an unsafe fn is an unsafe context, like an unsafe block.
+    fn visit_fn_body(&mut self, unsafety: Unsafety, body_id: BodyId) {
+        let body = self.tcx.hir().body(body_id);
+        let is_unsafe = unsafety == Unsafety::Unsafe;
+        if is_unsafe {
+            self.unsafe_blocks.push(body.value.span.source_callsite());
+            self.unsafe_depth += 1;
+        }
+        self.visit_body(body);
+        if is_unsafe {
+            self.unsafe_depth -= 1;
+        }
+    }
+
+    // Trait methods are dispatched through the trait even when resolved statically.
+    fn call_kind(&self, def_id: DefId, default: CallKind) -> CallKind {
+        if self.tcx.trait_of_item(def_id).is_some() {
+            CallKind::TraitDispatch
+        } else {
+            default
+        }
+    }
+
+    // Like clippy's `undocumented_unsafe_blocks`, one of the comment lines right above
+    // the line of the block says `SAFETY:`, e.g. above `let x = unsafe { .. };`.
+    fn is_justified(&self, span: Span) -> bool {
+        let lo = self
+            .tcx
+            .sess
+            .source_map()
+            .lookup_char_pos(span.source_callsite().lo());
+        // `get_line` counts from 0, so this is the line above the block.
+        let mut line = lo.line - 1;
+        while line > 0 {
+            line -= 1;
+            let Some(text) = lo.file.get_line(line) else {
+                return false;
+            };
+            let text = text.trim_start();
+            if !text.starts_with("//") {
+                return false;
+            }
+            if text.contains("SAFETY:") {
+                return true;
+            }
+        }
+        false
+    }
+
+    // The source without whitespace, so that reformatting or moving the code keeps it
+    fn digest(&self, span: Span) -> String {
+        let source = self
+            .tcx
+            .sess
+            .source_map()
+            .span_to_snippet(span.source_callsite())
+            .unwrap_or_default();
+        let text: String = source.chars().filter(|c| !c.is_whitespace()).collect();
+        fnv1a(&text)
+    }
+
+    fn source_span(&self, span: Span) -> SourceSpan {
+        let source_map = self.tcx.sess.source_map();
+        let span = span.source_callsite();
+        let lo = source_map.lookup_char_pos(span.lo());
+        let hi = source_map.lookup_char_pos(span.hi());
+
+        SourceSpan {
+            file: lo.file.name.prefer_local().to_string(),
+            line: lo.line,
+            col: lo.col_display + 1,
+            end_line: hi.line,
+            end_col: hi.col_display + 1,
+        }
+    }
+}
+
+// Cargo does not tell rustc which kind of target it builds,
+// so it is derived from the crate name, the `--test` flag and the location of the crate root.
+fn target_kind(tcx: TyCtxt<'_>) -> TargetKind {
+    let krate = tcx.crate_name(def_id::LOCAL_CRATE).to_string();
+    if krate.starts_with("build_script_") {
+        return TargetKind::BuildScript;
+    }
+
+    let src = tcx
+        .sess
+        .local_crate_source_file()
+        .and_then(|path| path.local_path().map(Path::to_path_buf))
+        .and_then(|path| path.canonicalize().ok())
+        .unwrap_or_default();
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let in_dir = |dir: &str| match src.strip_prefix(&manifest_dir) {
+        Ok(path) => path.starts_with(dir),
+        Err(_) => src.components().any(|c| c.as_os_str() == dir),
+    };
+
+    if in_dir("benches") {
+        TargetKind::Bench
+    } else if in_dir("examples") {
+        TargetKind::Example
+    } else if tcx.sess.opts.test {
+        TargetKind::Test
+    } else if tcx.crate_types().contains(&CrateType::Executable) {
+        TargetKind::Bin
+    } else {
+        TargetKind::Lib
+    }
+}
+
+// Cargo passes the package name and version to rustc, the source is derived from where it lives.
+fn package_id(krate: &str) -> PackageId {
+    let name = std::env::var("CARGO_PKG_NAME").unwrap_or_else(|_| krate.to_string());
+    let version = std::env::var("CARGO_PKG_VERSION").unwrap_or_else(|_| "0.0.0".to_string());
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let manifest_dir = Path::new(&manifest_dir);
+
+    // Cargo unpacks the registry and git dependencies in its home only.
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")))
+        .unwrap_or_default();
+    let registry = manifest_dir.strip_prefix(cargo_home.join("registry").join("src"));
+    let checkouts = manifest_dir.strip_prefix(cargo_home.join("git").join("checkouts"));
+
+    let source = if let Ok(path) = registry {
+        // <index>/<name>-<version>
+        let index = path.iter().next().unwrap_or_default();
+        format!("registry+{}", index.to_string_lossy())
+    } else if checkouts.is_ok() {
+        format!("git+{}", manifest_dir.display())
+    } else {
+        format!("path+{}", manifest_dir.display())
+    };
+
+    PackageId {
+        name,
+        version,
+        source,
+    }
+}
+
+fn fingerprint(tcx: TyCtxt<'_>) -> Fingerprint {
+    let unit = tcx.sess.opts.cg.extra_filename.trim_start_matches('-');
+
+    let mut features: Vec<_> = tcx
+        .sess
+        .psess
+        .config
+        .iter()
+        .filter(|(name, _)| *name == sym::feature)
+        .filter_map(|(_, value)| value.map(|value| value.to_string()))
+        .collect();
+    features.sort();
+
+    let mut hasher = DefaultHasher::new();
+    std::env::args().for_each(|arg| arg.hash(&mut hasher));
+    for file in tcx.sess.source_map().files().iter() {
+        if !file.is_imported() {
+            file.src_hash.hash(&mut hasher);
+        }
+    }
+    for cnum in tcx.crates(()) {
+        tcx.crate_hash(*cnum).hash(&mut hasher);
+    }
+
+    Fingerprint {
+        unit: unit.to_string(),
+        features,
+        hash: format!("{:016x}", hasher.finish()),
+    }
+}
+
+// Build scripts and their dependencies run on the host only,
+// dev-dependencies are only linked into tests.
+fn crate_kind(tcx: TyCtxt<'_>, target: &TargetKind) -> CrateKind {
+    if tcx.crate_types().contains(&CrateType::ProcMacro) {
+        return CrateKind::ProcMacro;
+    }
+
+    if *target == TargetKind::BuildScript {
+        return CrateKind::BuildDependency;
+    }
+
+    // With a target, cargo passes `--target` for the crates of the target only.
+    if config::build_target().is_some()
+        && !std::env::args().any(|arg| arg == "--target" || arg.starts_with("--target="))
+    {
+        return CrateKind::BuildDependency;
+    }
+
+    // The manifest directory tells apart packages of the same name and version from other sources.
+    let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from);
+    let Some(manifest_dir) = manifest_dir else {
+        return CrateKind::Target;
+    };
+    if config::host_packages().contains(&manifest_dir) {
+        CrateKind::BuildDependency
+    } else if config::dev_packages().contains(&manifest_dir) {
+        CrateKind::DevDependency
+    } else {
+        CrateKind::Target
     }
 }
 
 impl<'tcx> Visitor<'tcx> for Parser<'tcx> {
     fn visit_block(&mut self, block: &'tcx Block<'tcx>) {
-        if block.rules == UnsafeBlock(UnsafeSource::UserProvided) {
-            let owner_id = self.tcx.hir().get_parent_item(block.hir_id);
-            let def_path = self.tcx.def_path(owner_id.into());
-            let mut fn_name = def_path.to_string_no_crate_verbose();
-            if fn_name.contains("impl") {
-                fn_name = format!("::{}", self.tcx.def_path_str(owner_id));
-            };
-            self.record.add_item(UnsafeKind::Block, fn_name);
+        let is_unsafe = block.rules == UnsafeBlock(UnsafeSource::UserProvided);
+        if is_unsafe {
+            let (owner_id, owner) = self.context_owner(block.hir_id);
+            let id = self.node(owner_id.to_def_id());
+            let span = self.source_span(block.span);
+            let justified = self.is_justified(block.span);
+            let digest = self.digest(block.span);
+            self.record
+                .add_block(id.clone(), span.clone(), justified, digest);
+            self.record.add_item(UnsafeKind::Block, id, owner, span);
+            self.unsafe_blocks.push(block.span.source_callsite());
+            self.unsafe_depth += 1;
         }
         intravisit::walk_block(self, block);
+        if is_unsafe {
+            self.unsafe_depth -= 1;
+        }
     }
 
     fn visit_impl_item(&mut self, item: &'tcx ImplItem<'tcx>) {
-        if let rustc_hir::ImplItemKind::Fn(_, body_id) = &item.kind {
+        self.unsafe_blocks.clear();
+        if let rustc_hir::ImplItemKind::Fn(sig, body_id) = &item.kind {
+            self.visit_fn_body(sig.header.unsafety, *body_id);
+        }
+
+        if let rustc_hir::ImplItemKind::Const(_, body_id) = &item.kind {
+            self.add_const_context(item.owner_id.def_id);
             let body = self.tcx.hir().body(*body_id);
             self.visit_body(body);
         }
@@ -62,20 +474,27 @@ impl<'tcx> Visitor<'tcx> for Parser<'tcx> {
     }
 
     fn visit_trait_item(&mut self, item: &'tcx TraitItem<'tcx>) {
+        self.unsafe_blocks.clear();
         if let rustc_hir::TraitItemKind::Fn(fn_sig, trait_fn) = &item.kind {
             if let TraitFn::Provided(body_id) = trait_fn {
-                let body = self.tcx.hir().body(*body_id);
-                self.visit_body(body);
+                self.visit_fn_body(fn_sig.header.unsafety, *body_id);
             }
 
             if let TraitFn::Required(_) = trait_fn {
                 if fn_sig.header.unsafety == Unsafety::Unsafe {
-                    let def_path = self.tcx.def_path(item.owner_id.to_def_id());
-                    let fn_name = def_path.to_string_no_crate_verbose();
-                    self.record.add_item(UnsafeKind::Function, fn_name);
+                    let id = self.node(item.owner_id.to_def_id());
+                    let span = self.source_span(item.span);
+                    self.record
+                        .add_item(UnsafeKind::Function, id, OwnerKind::Fn, span);
                 }
             }
         }
+
+        if let rustc_hir::TraitItemKind::Const(_, Some(body_id)) = &item.kind {
+            self.add_const_context(item.owner_id.def_id);
+            let body = self.tcx.hir().body(*body_id);
+            self.visit_body(body);
+        }
         intravisit::walk_trait_item(self, item);
     }
 
@@ -84,99 +503,155 @@ impl<'tcx> Visitor<'tcx> for Parser<'tcx> {
         fk: FnKind<'tcx>,
         fd: &'tcx FnDecl<'tcx>,
         b: BodyId,
-        _: Span,
+        span: Span,
         id: LocalDefId,
     ) {
+        self.add_drop_edges(id);
+
         let header = match fk {
             intravisit::FnKind::ItemFn(_, _, header) => header,
             intravisit::FnKind::Method(_, sig) => sig.header,
             _ => return,
         };
 
-        let def_path = self.tcx.def_path(id.to_def_id());
-        let mut fn_name = def_path.to_string_no_crate_verbose();
-        if fn_name.contains("impl") {
-            fn_name = format!("::{}", self.tcx.def_path_str(id));
-        };
+        let node = self.node(id.to_def_id());
+        let owner = self.owner_kind(id);
 
         if header.unsafety == Unsafety::Unsafe {
-            self.record.add_item(UnsafeKind::Function, fn_name.clone());
+            let span = self.source_span(span);
+            self.record
+                .add_item(UnsafeKind::Function, node.clone(), owner.clone(), span);
         }
 
-        self.record.add_edge(fn_name, String::new());
+        if owner == OwnerKind::ConstFn && self.is_exported(id) {
+            self.record.add_exported(node.clone());
+        }
+        if owner.is_const() {
+            self.record.add_const_context(node.clone(), owner);
+        }
+
+        self.record.add_fn(node);
 
         intravisit::walk_fn(self, fk, fd, b, id);
     }
 
     fn visit_item(&mut self, item: &'tcx Item<'tcx>) {
-        if let ItemKind::Fn(_, _, body_id) = &item.kind {
+        self.unsafe_blocks.clear();
+        if let ItemKind::Fn(sig, _, body_id) = &item.kind {
+            self.visit_fn_body(sig.header.unsafety, *body_id);
+        }
+
+        if let ItemKind::Const(_, _, body_id) | ItemKind::Static(_, _, body_id) = &item.kind {
+            self.add_const_context(item.owner_id.def_id);
             let body = self.tcx.hir().body(*body_id);
             self.visit_body(body);
         }
 
         if let ItemKind::Trait(_, unsafety, _, _, _) = &item.kind {
             if *unsafety == Unsafety::Unsafe {
-                let def_path = self.tcx.def_path(item.owner_id.to_def_id());
-                let trait_name = def_path.to_string_no_crate_verbose();
-                self.record.add_item(UnsafeKind::Trait, trait_name);
+                let id = self.node(item.owner_id.to_def_id());
+                let span = self.source_span(item.span);
+                self.record
+                    .add_item(UnsafeKind::Trait, id, OwnerKind::Trait, span);
             }
         }
 
         if let ItemKind::Impl(ref_) = &item.kind {
             if ref_.unsafety == Unsafety::Unsafe {
-                let impl_name = format!("::{}", self.tcx.def_path_str(item.owner_id));
-                self.record.add_item(UnsafeKind::Impl, impl_name);
+                let id = self.node(item.owner_id.to_def_id());
+                let span = self.source_span(item.span);
+                self.record
+                    .add_item(UnsafeKind::Impl, id, OwnerKind::Impl, span);
+            }
+
+            let trait_id = ref_.of_trait.as_ref().and_then(|t| t.trait_def_id());
+            if trait_id.is_some() && trait_id == self.tcx.lang_items().drop_trait() {
+                let ty = self.tcx.type_of(item.owner_id).instantiate_identity();
+                for impl_item in ref_.items {
+                    if impl_item.ident.as_str() == "drop" {
+                        let drop_fn = self.node(impl_item.id.owner_id.to_def_id());
+                        self.record.add_drop_impl(ty.to_string(), drop_fn);
+                    }
+                }
             }
         }
 
         intravisit::walk_item(self, item);
     }
 
+    // e.g. array lengths and enum discriminants
+    fn visit_anon_const(&mut self, c: &'tcx AnonConst) {
+        self.add_const_context(c.def_id);
+        let body = self.tcx.hir().body(c.body);
+        self.visit_body(body);
+        intravisit::walk_anon_const(self, c);
+    }
+
+    fn visit_inline_const(&mut self, c: &'tcx ConstBlock) {
+        self.add_const_context(c.def_id);
+        let body = self.tcx.hir().body(c.body);
+        self.visit_body(body);
+        intravisit::walk_inline_const(self, c);
+    }
+
     fn visit_expr(&mut self, expr: &'tcx Expr<'tcx>) {
+        // The function defining a closure calls it, directly or through the functions it is given to.
         if let ExprKind::Closure(c) = &expr.kind {
+            let closure = self.node(c.def_id.to_def_id());
+            self.record.add_fn(closure.clone());
+            self.add_call(expr.hir_id, closure, CallKind::Closure, expr.span);
             let body = self.tcx.hir().body(c.body);
             self.visit_body(body);
         }
 
-        // TODO: MethodCall
-        if let ExprKind::Call(path_expr, _) = &expr.kind {
-            if let ExprKind::Path(QPath::Resolved(_, path)) = &path_expr.kind {
-                if let Some(def_id) = path.res.opt_def_id() {
-                    let owner_id = self.tcx.hir().get_parent_item(expr.hir_id);
-                    let mut caller = self
-                        .tcx
-                        .def_path(owner_id.into())
-                        .to_string_no_crate_verbose();
-
-                    if caller.contains("impl") {
-                        caller = format!("::{}", self.tcx.def_path_str(owner_id));
-                    };
-
-                    let def_path = self.tcx.def_path(def_id);
-                    let crate_name = self.tcx.crate_name(def_path.krate).to_string();
-                    let fn_name = def_path.to_string_no_crate_verbose();
-                    let callee = format!("{}{}", crate_name, fn_name);
-                    self.record.add_edge(caller, callee);
-                }
+        // The callee is classified by its type, which also covers type-relative paths
+        // (e.g. `Vec::new`) and locals holding functions, pointers or closures.
+        let body_owner = self.tcx.hir().enclosing_body_owner(expr.hir_id);
+        if let ExprKind::Call(callee_expr, _) = &expr.kind {
+            let typeck = self.tcx.typeck(body_owner);
+            let ty = typeck.expr_ty(callee_expr);
+            let call = match ty.kind() {
+                ty::FnDef(def_id, _) => Some((
+                    self.node(*def_id),
+                    self.call_kind(*def_id, CallKind::Direct),
+                )),
+                ty::FnPtr(_) => Some((self.fn_pointer_node(ty), CallKind::FnPointer)),
+                ty::Closure(def_id, _) => Some((self.node(*def_id), CallKind::Closure)),
+                // overloaded calls, e.g. `dyn Fn` or `F: Fn`
+                _ => typeck
+                    .type_dependent_def_id(expr.hir_id)
+                    .map(|def_id| (self.node(def_id), CallKind::TraitDispatch)),
+            };
+
+            match call {
+                Some((callee, kind)) => self.add_call(expr.hir_id, callee, kind, expr.span),
+                None => self.add_diagnostic(expr.hir_id, DiagnosticKind::UnresolvedCall, expr.span),
             }
         }
 
         // To resolve the called method to a DefId,
         // call type_dependent_def_id with the hir_id of the MethodCall node itself.
         if let ExprKind::MethodCall(_, _, _, _) = &expr.kind {
-            let owner_id = expr.hir_id.owner.def_id;
             let def_id = self
                 .tcx
-                .typeck(owner_id)
-                .type_dependent_def_id(expr.hir_id)
-                .unwrap();
-            let def_path = self.tcx.def_path(def_id);
-            let method_name = self.tcx.def_path_str(def_id);
-            let crate_name = self.tcx.crate_name(def_path.krate).to_string();
-            let callee = format!("{}::{}", crate_name, method_name);
-
-            let caller = format!("::{}", self.tcx.def_path_str(owner_id));
-            self.record.add_edge(caller, callee);
+                .typeck(body_owner)
+                .type_dependent_def_id(expr.hir_id);
+            match def_id {
+                Some(def_id) => {
+                    let callee = self.node(def_id);
+                    let kind = self.call_kind(def_id, CallKind::Method);
+                    self.add_call(expr.hir_id, callee, kind, expr.span);
+                }
+                None => self.add_diagnostic(expr.hir_id, DiagnosticKind::UnresolvedCall, expr.span),
+            }
+        }
+
+        if let Some(kind) = uninit::check_expr(self.tcx, body_owner, expr, &mut self.with_capacity)
+        {
+            let (owner_id, _) = self.context_owner(expr.hir_id);
+            let owner = self.node(owner_id.to_def_id());
+            let span = self.source_span(expr.span);
+            self.record.add_finding(owner, kind, span);
         }
 
         intravisit::walk_expr(self, expr);
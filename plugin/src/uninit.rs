@@ -0,0 +1,121 @@
+use utrace_common::FindingKind;
+
+use rustc_hir::def::Res;
+use rustc_hir::def_id::{DefId, LocalDefId};
+use rustc_hir::{Expr, ExprKind, HirId, Node, PatKind, QPath};
+use rustc_middle::ty::layout::ValidityRequirement;
+use rustc_middle::ty::{Ty, TyCtxt};
+use rustc_span::sym;
+
+// Detects well-known ways of producing uninitialized memory.
+// `with_capacity` tracks the variables bound to a `Vec` created by `Vec::with_capacity`,
+// so that a later `set_len` on one of them can be reported.
+pub fn check_expr<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    owner: LocalDefId,
+    expr: &'tcx Expr<'tcx>,
+    with_capacity: &mut Vec<HirId>,
+) -> Option<FindingKind> {
+    let typeck = tcx.typeck(owner);
+
+    match &expr.kind {
+        ExprKind::Call(path_expr, _) => {
+            let def_id = callee_def_id(tcx, owner, path_expr)?;
+
+            if tcx.is_diagnostic_item(sym::mem_uninitialized, def_id) {
+                return Some(FindingKind::MemUninitialized);
+            }
+
+            if tcx.is_diagnostic_item(sym::mem_zeroed, def_id) {
+                let ty = typeck.node_args(path_expr.hir_id).type_at(0);
+                if !permits_zero_init(tcx, owner, ty) {
+                    return Some(FindingKind::MemZeroed { ty: ty.to_string() });
+                }
+            }
+
+            if is_vec_method(tcx, def_id, "with_capacity") {
+                if let Some(binding) = bound_variable(tcx, expr) {
+                    with_capacity.push(binding);
+                }
+            }
+        }
+        ExprKind::MethodCall(_, receiver, _, _) => {
+            let def_id = typeck.type_dependent_def_id(expr.hir_id)?;
+
+            if tcx.is_diagnostic_item(sym::assume_init, def_id) {
+                if let ExprKind::Call(path_expr, _) = &receiver.kind {
+                    let callee = callee_def_id(tcx, owner, path_expr)?;
+                    if tcx.is_diagnostic_item(sym::maybe_uninit_uninit, callee) {
+                        return Some(FindingKind::AssumeInitUninit);
+                    }
+                }
+            }
+
+            if is_vec_method(tcx, def_id, "set_len")
+                && variable(receiver).is_some_and(|binding| with_capacity.contains(&binding))
+            {
+                return Some(FindingKind::VecSetLen);
+            }
+        }
+        _ => {}
+    }
+
+    None
+}
+
+fn callee_def_id<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    owner: LocalDefId,
+    path_expr: &'tcx Expr<'tcx>,
+) -> Option<DefId> {
+    if let ExprKind::Path(qpath) = &path_expr.kind {
+        if let Res::Def(_, def_id) = tcx.typeck(owner).qpath_res(qpath, path_expr.hir_id) {
+            return Some(def_id);
+        }
+    }
+    None
+}
+
+// The variable `expr` is bound to, by `let` or by an assignment
+fn bound_variable(tcx: TyCtxt<'_>, expr: &Expr<'_>) -> Option<HirId> {
+    match tcx.parent_hir_node(expr.hir_id) {
+        Node::LetStmt(local) => match local.pat.kind {
+            PatKind::Binding(_, binding, _, _) => Some(binding),
+            _ => None,
+        },
+        Node::Expr(Expr {
+            kind: ExprKind::Assign(place, value, _),
+            ..
+        }) if value.hir_id == expr.hir_id => variable(place),
+        _ => None,
+    }
+}
+
+// The variable `expr` refers to, also through a reference, e.g. `v` or `&mut v`
+fn variable(expr: &Expr<'_>) -> Option<HirId> {
+    match &expr.kind {
+        ExprKind::AddrOf(_, _, inner) => variable(inner),
+        ExprKind::Path(QPath::Resolved(None, path)) => match path.res {
+            Res::Local(binding) => Some(binding),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_vec_method(tcx: TyCtxt<'_>, def_id: DefId, name: &str) -> bool {
+    if tcx.item_name(def_id).as_str() != name {
+        return false;
+    }
+
+    tcx.impl_of_method(def_id)
+        .and_then(|impl_id| tcx.type_of(impl_id).instantiate_identity().ty_adt_def())
+        .is_some_and(|adt| tcx.is_diagnostic_item(sym::Vec, adt.did()))
+}
+
+// Generic types whose layout is unknown are given the benefit of the doubt.
+fn permits_zero_init<'tcx>(tcx: TyCtxt<'tcx>, owner: LocalDefId, ty: Ty<'tcx>) -> bool {
+    let param_env = tcx.param_env(owner);
+    tcx.check_validity_requirement((ValidityRequirement::Zero, param_env.and(ty)))
+        .unwrap_or(true)
+}
@@ -0,0 +1,20 @@
+pub fn with_capacity(n: usize) -> Vec<u8> {
+    let mut v = Vec::with_capacity(n);
+    unsafe { v.set_len(n) };
+    v
+}
+
+pub fn other_vec(n: usize) -> Vec<u8> {
+    let _reserved: Vec<u8> = Vec::with_capacity(n);
+    let mut v = vec![0; n];
+    unsafe { v.set_len(n / 2) };
+    v
+}
+
+pub fn assigned(n: usize) -> Vec<u8> {
+    let mut v = vec![0; 1];
+    v.clear();
+    v = Vec::with_capacity(n);
+    unsafe { (&mut v).set_len(n) };
+    v
+}
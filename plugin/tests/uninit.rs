@@ -0,0 +1,61 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use utrace_common::{config, FindingKind, Record};
+
+// Compiles `fixture` with the plugin into `out_dir`, one per test, and returns the record of the crate.
+fn analyse(fixture: &str, out_dir: &str) -> Record {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(fixture);
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(out_dir);
+    let _ = fs::remove_dir_all(&out_dir);
+    fs::create_dir_all(&out_dir).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_utrace_plugin"))
+        .arg("--crate-type=lib")
+        .arg("--edition=2021")
+        .arg("--out-dir")
+        .arg(out_dir.join("build"))
+        .arg(&fixture)
+        .env("UTRACE_OUT_DIR", &out_dir)
+        .env("LD_LIBRARY_PATH", config::sysroot_lib())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let record = fs::read_dir(&out_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|ext| ext == "record"))
+        .unwrap();
+    Record::load(record.to_str().unwrap()).unwrap()
+}
+
+// Lines of the `set_len` findings in `set_len.rs`
+fn set_len_lines(out_dir: &str) -> Vec<usize> {
+    let record = analyse("set_len.rs", out_dir);
+    let mut lines: Vec<_> = record
+        .findings
+        .values()
+        .flatten()
+        .filter(|finding| finding.kind == FindingKind::VecSetLen)
+        .map(|finding| finding.span.line)
+        .collect();
+    lines.sort();
+    lines
+}
+
+#[test]
+fn set_len_of_vec_with_capacity() {
+    let lines = set_len_lines("with_capacity");
+    assert!(lines.contains(&3));
+    assert!(lines.contains(&18));
+}
+
+#[test]
+fn set_len_of_other_vec() {
+    // `other_vec` also calls `with_capacity`, but for another `Vec` than the one given to `set_len`.
+    let lines = set_len_lines("other_vec");
+    assert!(!lines.contains(&10));
+}